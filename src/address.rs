@@ -0,0 +1,115 @@
+use regex::Regex;
+use std::sync::OnceLock;
+
+// MAX_REGIONS 地址开头最多检查几级行政区
+const MAX_REGIONS: usize = 8;
+
+// postcode_re 地址末尾的邮编，前面需要有分隔符或“邮编”，避免去掉门牌号
+fn postcode_re() -> &'static Regex {
+    static RE: OnceLock<Regex> = OnceLock::new();
+    RE.get_or_init(|| {
+        Regex::new(r"(?:邮编[:：]?\s*|[\s,，(（])\d{6}[)）]?$").expect("postcode regex is valid")
+    })
+}
+
+// region_re 一级行政区，如 “广东省”、“深圳市”、“南山区”、“内蒙古自治区”
+fn region_re() -> &'static Regex {
+    static RE: OnceLock<Regex> = OnceLock::new();
+    RE.get_or_init(|| {
+        Regex::new(r"^.{1,9}?(?:特别行政区|自治区|自治州|自治县|省|市|区|县|旗)")
+            .expect("region regex is valid")
+    })
+}
+
+// fold_width 全角字符转半角，号码规范化时也使用
+pub(crate) fn fold_width(c: char) -> char {
+    match c {
+        '\u{3000}' => ' ',
+        '\u{FF01}'..='\u{FF5E}' => char::from_u32(c as u32 - 0xFEE0).unwrap_or(c),
+        _ => c,
+    }
+}
+
+fn is_separator(c: char) -> bool {
+    c.is_whitespace() || c.is_ascii_punctuation() || "，。、；：“”‘’【】《》—…·".contains(c)
+}
+
+// dedup_regions 去掉开头重复的省市区，如 “广东省深圳市南山区 深圳市南山区xx路”
+fn dedup_regions(address: &str) -> String {
+    let mut regions: Vec<&str> = Vec::new();
+    let mut res = String::new();
+    let mut rest = address;
+    for _ in 0..MAX_REGIONS {
+        let region = match region_re().find(rest) {
+            Some(m) => m.as_str(),
+            None => break,
+        };
+        if !regions.contains(&region) {
+            regions.push(region);
+            res += region;
+        }
+        rest = &rest[region.len()..];
+    }
+    res + rest
+}
+
+// normalize 规范化收货地址，只用于判断是否为同一地址，输出仍使用原始地址：
+// 全角转半角，去掉空白与标点，去掉末尾的邮编和开头重复的省市区，英文转小写
+pub fn normalize(address: &str) -> String {
+    let folded: String = address.chars().map(fold_width).collect();
+    let folded = folded.trim();
+    let without_postcode = postcode_re().replace(folded, "");
+    let cleaned: String = without_postcode
+        .chars()
+        .filter(|c| !is_separator(*c))
+        .flat_map(char::to_lowercase)
+        .collect();
+    dedup_regions(&cleaned)
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn test_normalize() {
+        let expected = "广东省深圳市南山区科技园1号a座";
+        assert_eq!(normalize("广东省深圳市南山区科技园1号A座"), expected);
+        // 全角、空格与标点
+        assert_eq!(
+            normalize("广东省 深圳市 南山区　科技园１号Ａ座，"),
+            expected
+        );
+        // 重复的省市区
+        assert_eq!(
+            normalize("广东省 深圳市 南山区 广东省深圳市南山区科技园1号A座"),
+            expected
+        );
+        assert_eq!(
+            normalize("广东省深圳市南山区深圳市南山区科技园1号A座"),
+            expected
+        );
+        // 邮编
+        assert_eq!(normalize("广东省深圳市南山区科技园1号A座 518000"), expected);
+        assert_eq!(normalize("广东省深圳市南山区科技园1号A座,000000"), expected);
+        assert_eq!(
+            normalize("广东省深圳市南山区科技园1号A座(邮编:518000)"),
+            expected
+        );
+
+        // 门牌号不是邮编
+        assert_eq!(
+            normalize("深圳市南山区科技路100001号"),
+            "深圳市南山区科技路100001号"
+        );
+        assert_eq!(
+            normalize("内蒙古自治区 呼和浩特市 内蒙古自治区呼和浩特市xx路"),
+            "内蒙古自治区呼和浩特市xx路"
+        );
+        assert_ne!(
+            normalize("广东省深圳市南山区1号"),
+            normalize("广东省深圳市福田区1号")
+        );
+    }
+}
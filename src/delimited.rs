@@ -0,0 +1,178 @@
+use crate::input::{Row, Rows};
+use calamine::Data;
+use std::{fs, io, path::Path};
+
+// Encoding 文本导出文件的编码
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Encoding {
+    Utf8Bom,
+    Utf8,
+    Gbk,
+    Gb18030,
+}
+
+impl Encoding {
+    pub fn name(self) -> &'static str {
+        match self {
+            Encoding::Utf8Bom => "UTF-8 (BOM)",
+            Encoding::Utf8 => "UTF-8",
+            Encoding::Gbk => "GBK",
+            Encoding::Gb18030 => "GB18030",
+        }
+    }
+}
+
+const UTF8_BOM: &[u8] = b"\xEF\xBB\xBF";
+
+// is_delimited 根据扩展名判断是否为 csv/tsv 文件
+pub fn is_delimited<P>(path: P) -> bool
+where
+    P: AsRef<Path>,
+{
+    match path.as_ref().extension() {
+        Some(ext) => {
+            let ext = ext.to_string_lossy().to_lowercase();
+            ext == "csv" || ext == "tsv"
+        }
+        None => false,
+    }
+}
+
+// detect_encoding 识别编码：有 BOM 或者是合法的 UTF-8 时按 UTF-8 处理，
+// 否则按 GBK 处理，出现四字节编码时为 GB18030
+pub fn detect_encoding(bytes: &[u8]) -> Encoding {
+    if bytes.starts_with(UTF8_BOM) {
+        return Encoding::Utf8Bom;
+    }
+    if std::str::from_utf8(bytes).is_ok() {
+        return Encoding::Utf8;
+    }
+
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] < 0x80 {
+            i += 1;
+            continue;
+        }
+        if i + 1 < bytes.len() && (0x30..=0x39).contains(&bytes[i + 1]) {
+            return Encoding::Gb18030;
+        }
+        i += 2;
+    }
+    Encoding::Gbk
+}
+
+// decode 按识别出的编码解码
+pub fn decode(bytes: &[u8]) -> Result<(String, Encoding), String> {
+    let encoding = detect_encoding(bytes);
+    let content = match encoding {
+        Encoding::Utf8Bom => String::from_utf8_lossy(&bytes[UTF8_BOM.len()..]).into_owned(),
+        Encoding::Utf8 => String::from_utf8_lossy(bytes).into_owned(),
+        // encoding_rs 中 GBK 与 GB18030 共用同一个解码器
+        Encoding::Gbk | Encoding::Gb18030 => encoding_rs::GB18030
+            .decode_without_bom_handling_and_without_replacement(bytes)
+            .ok_or_else(|| String::from("unsupported encoding, expect UTF-8, GBK or GB18030"))?
+            .into_owned(),
+    };
+    Ok((content, encoding))
+}
+
+// detect_delimiter tsv 文件或首行中制表符多于逗号时按制表符分隔
+fn detect_delimiter(path: &Path, content: &str) -> u8 {
+    let is_tsv = path
+        .extension()
+        .map(|ext| ext.to_string_lossy().to_lowercase() == "tsv")
+        .unwrap_or(false);
+    let first_line = content.lines().next().unwrap_or("");
+    if is_tsv || first_line.matches('\t').count() > first_line.matches(',').count() {
+        b'\t'
+    } else {
+        b','
+    }
+}
+
+// parse 逐行解析文本内容，不需要一次读出所有行
+pub fn parse(content: String, delimiter: u8) -> impl Iterator<Item = Result<Row, String>> {
+    csv::ReaderBuilder::new()
+        .has_headers(false)
+        .flexible(true)
+        .delimiter(delimiter)
+        .from_reader(io::Cursor::new(content.into_bytes()))
+        .into_records()
+        .enumerate()
+        .map(|(i, record)| {
+            let record = record.map_err(|err| format!("parse csv failed: {}", err))?;
+            Ok((i as u32, record.iter().map(to_cell).collect()))
+        })
+}
+
+// to_cell 空白单元格为 Empty，与 xls 中的空单元格一致
+pub fn to_cell(value: &str) -> Data {
+    match value.trim() {
+        "" => Data::Empty,
+        _ => Data::String(String::from(value)),
+    }
+}
+
+// read_rows 逐行读取 csv/tsv 文件，解码后的文本需要整个读入内存
+pub fn read_rows<P>(path: P) -> Result<Rows, String>
+where
+    P: AsRef<Path>,
+{
+    let path = path.as_ref();
+    let bytes = fs::read(path).map_err(|err| format!("can't open file: {}", err))?;
+    let (content, encoding) = decode(&bytes)?;
+    println!("encoding: {}", encoding.name());
+    let delimiter = detect_delimiter(path, &content);
+    Ok(Box::new(parse(content, delimiter)))
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use calamine::DataType;
+
+    #[test]
+    fn test_decode() {
+        let (content, encoding) = decode("\u{feff}订单编号".as_bytes()).unwrap();
+        assert_eq!(encoding, Encoding::Utf8Bom);
+        assert_eq!(content, "订单编号");
+
+        let (content, encoding) = decode("订单编号".as_bytes()).unwrap();
+        assert_eq!(encoding, Encoding::Utf8);
+        assert_eq!(content, "订单编号");
+
+        let (bytes, _, _) = encoding_rs::GBK.encode("订单编号,收货人");
+        let (content, encoding) = decode(&bytes).unwrap();
+        assert_eq!(encoding, Encoding::Gbk);
+        assert_eq!(content, "订单编号,收货人");
+
+        // 𠀀 不在 GBK 中，GB18030 使用四字节编码
+        let (bytes, _, _) = encoding_rs::GB18030.encode("收货人𠀀");
+        let (content, encoding) = decode(&bytes).unwrap();
+        assert_eq!(encoding, Encoding::Gb18030);
+        assert_eq!(content, "收货人𠀀");
+    }
+
+    #[test]
+    fn test_parse() {
+        let content = "订单编号,货品标题\n1001,\"AX199 红色, 110cm\"\n,\n";
+        let rows: Vec<Row> = parse(String::from(content), b',')
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(rows.len(), 3);
+        assert_eq!(rows[1].0, 1);
+        assert_eq!(rows[1].1[1].get_string(), Some("AX199 红色, 110cm"));
+        assert_eq!(rows[2].1, vec![Data::Empty, Data::Empty]);
+
+        let content = "订单编号\t数量\n1001\t2\n";
+        assert_eq!(detect_delimiter(Path::new("a.csv"), content), b'\t');
+        assert_eq!(detect_delimiter(Path::new("a.csv"), "a,b"), b',');
+        assert_eq!(detect_delimiter(Path::new("a.tsv"), "a,b"), b'\t');
+
+        // 与目录中读取的文件类型一致
+        assert!(is_delimited("a.CSV") && is_delimited("a.tsv"));
+        assert!(!is_delimited("a.txt"));
+    }
+}
@@ -0,0 +1,60 @@
+use crate::xlsx::{self, Row};
+use std::fmt;
+
+// Diagnostic 读取订单时字段缺失或格式不对，使用了默认值
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    pub file: String,    // 文件
+    pub sheet: String,   // sheet 名
+    pub row: u32,        // sheet 中的行号，从 1 开始
+    pub column: String,  // 列名
+    pub raw: String,     // 原始值
+    pub message: String, // 使用了什么默认值
+    pub required: bool,  // 是否为必填字段
+}
+
+impl Diagnostic {
+    pub fn excel_title_row() -> Row {
+        xlsx::row!["文件", "Sheet", "行号", "列名", "原始值", "说明", "必填"]
+    }
+
+    pub fn as_excel_row(&self) -> Row {
+        xlsx::row![
+            self.file.clone(),
+            self.sheet.clone(),
+            self.row as f64,
+            self.column.clone(),
+            self.raw.clone(),
+            self.message.clone(),
+            if self.required { "是" } else { "" }
+        ]
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} {} row {}: {} {:?} {}{}",
+            self.file,
+            self.sheet,
+            self.row,
+            self.column,
+            self.raw,
+            self.message,
+            if self.required { " (required)" } else { "" }
+        )
+    }
+}
+
+// check_strict 严格模式下必填字段缺失时拒绝输出
+pub fn check_strict(diagnostics: &[Diagnostic]) -> Result<(), String> {
+    let count = diagnostics.iter().filter(|d| d.required).count();
+    if count > 0 {
+        return Err(format!(
+            "strict mode: {} required field(s) missing or invalid",
+            count
+        ));
+    }
+    Ok(())
+}
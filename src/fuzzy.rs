@@ -0,0 +1,432 @@
+use crate::address;
+use crate::opr::{MergeKey, MergeRecord, MergeSide};
+use crate::order::Order;
+use crate::overrides::Overrides;
+use crate::phone::Phone;
+use crate::xlsx::{self, Row};
+use serde::Deserialize;
+use std::collections::{HashMap, HashSet};
+
+// FuzzySettings 疑似同一买家的阈值，相似度在 0 到 1 之间
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct FuzzySettings {
+    pub suggest: f64,            // 不低于此值的订单列入疑似同一买家
+    pub auto_merge: Option<f64>, // 不低于此值且满足合并条件时自动合并，不配置则不自动合并
+}
+
+impl Default for FuzzySettings {
+    fn default() -> FuzzySettings {
+        FuzzySettings {
+            suggest: 0.8,
+            auto_merge: None,
+        }
+    }
+}
+
+impl FuzzySettings {
+    // check 低于 suggest 的订单不会列入疑似同一买家，auto_merge 低于 suggest 时无法生效
+    pub fn check(&self) -> Result<(), String> {
+        match self.auto_merge {
+            Some(auto_merge) if auto_merge < self.suggest => Err(format!(
+                "fuzzy auto_merge {} is lower than suggest {}",
+                auto_merge, self.suggest
+            )),
+            _ => Ok(()),
+        }
+    }
+}
+
+// 各字段在相似度中的权重
+const CONSIGNEE_WEIGHT: f64 = 0.3;
+const ADDRESS_WEIGHT: f64 = 0.4;
+const PHONE_WEIGHT: f64 = 0.3;
+
+// similarity 按编辑距离计算两个字符串的相似度，都为空时为 1
+pub fn similarity(a: &str, b: &str) -> f64 {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    let len = a.len().max(b.len());
+    if len == 0 {
+        return 1.0;
+    }
+    let mut prev: Vec<usize> = (0..=b.len()).collect();
+    let mut cur = vec![0; b.len() + 1];
+    for (i, ca) in a.iter().enumerate() {
+        cur[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let replace = prev[j] + usize::from(ca != cb);
+            cur[j + 1] = replace.min(prev[j + 1] + 1).min(cur[j] + 1);
+        }
+        std::mem::swap(&mut prev, &mut cur);
+    }
+    1.0 - prev[b.len()] as f64 / len as f64
+}
+
+// Buyer 用于比较的买家信息
+struct Buyer {
+    consignee: String,
+    address: String,
+    phone: Option<Phone>, // 打码或为空时无法比较
+}
+
+impl Buyer {
+    fn new(order: &Order) -> Buyer {
+        let consignee: String = order
+            .consignee
+            .chars()
+            .filter(|c| !c.is_whitespace())
+            .flat_map(char::to_lowercase)
+            .collect();
+        let phone = match Phone::parse(order.contact_phone()) {
+            Phone::Empty | Phone::Masked(_) => None,
+            phone => Some(phone),
+        };
+        Buyer {
+            consignee,
+            address: address::normalize(&order.shipping_address),
+            phone,
+        }
+    }
+}
+
+// SuggestSide 疑似同一买家的一方
+#[derive(Debug)]
+pub struct SuggestSide {
+    pub id: String,
+    pub consignee: String,
+    pub shipping_address: String,
+    pub phone: String,
+    pub status: String,
+}
+
+impl SuggestSide {
+    fn new(order: &Order) -> SuggestSide {
+        SuggestSide {
+            id: order.id.clone(),
+            consignee: order.consignee.clone(),
+            shipping_address: order.shipping_address.clone(),
+            phone: order.contact_phone().to_string(),
+            status: order.status.clone(),
+        }
+    }
+}
+
+// Suggestion 疑似同一买家的两个订单
+#[derive(Debug)]
+pub struct Suggestion {
+    pub score: f64,           // 相似度
+    pub consignee_score: f64, // 收货人相似度
+    pub address_score: f64,   // 地址相似度
+    pub same_phone: bool,     // 号码相同
+    pub first: SuggestSide,
+    pub second: SuggestSide,
+    pub auto_merged: bool, // 是否已自动合并
+}
+
+impl Suggestion {
+    pub fn excel_title_row() -> Row {
+        xlsx::row![
+            "相似度",
+            "收货人相似度",
+            "地址相似度",
+            "号码相同",
+            "已自动合并",
+            "订单编号",
+            "收货人姓名",
+            "收货地址",
+            "联系手机",
+            "订单状态",
+            "疑似订单编号",
+            "疑似收货人姓名",
+            "疑似收货地址",
+            "疑似联系手机",
+            "疑似订单状态"
+        ]
+    }
+
+    pub fn as_excel_row(&self) -> Row {
+        let yes_no = |v: bool| if v { "是" } else { "否" };
+        let (first, second) = (&self.first, &self.second);
+        xlsx::row![
+            self.score,
+            self.consignee_score,
+            self.address_score,
+            yes_no(self.same_phone),
+            yes_no(self.auto_merged),
+            first.id.clone(),
+            first.consignee.clone(),
+            first.shipping_address.clone(),
+            first.phone.clone(),
+            first.status.clone(),
+            second.id.clone(),
+            second.consignee.clone(),
+            second.shipping_address.clone(),
+            second.phone.clone(),
+            second.status.clone()
+        ]
+    }
+
+    // matched 自动合并时写入合并说明的字段
+    fn matched(&self, key: &MergeKey) -> Vec<&'static str> {
+        let mut matched = vec![
+            if self.consignee_score == 1.0 {
+                "收货人姓名"
+            } else {
+                "收货人姓名(相似)"
+            },
+            if self.address_score == 1.0 {
+                "收货地址(规范化后)"
+            } else {
+                "收货地址(相似)"
+            },
+        ];
+        matched.extend(key.exact_fields());
+        if self.same_phone {
+            matched.push("联系手机");
+        }
+        matched
+    }
+}
+
+// round 相似度保留两位小数
+fn round(v: f64) -> f64 {
+    (v * 100.0).round() / 100.0
+}
+
+// Block 号码相同或收货人相同的订单分为一块
+#[derive(PartialEq, Eq, Hash)]
+enum Block<'a> {
+    Phone(&'a Phone),
+    Consignee(&'a str),
+}
+
+// candidates 只比较号码相同或收货人相同的订单，避免两两比较所有订单
+fn candidates(buyers: &[Buyer]) -> Vec<(usize, usize)> {
+    let mut blocks = HashMap::<Block, Vec<usize>>::new();
+    for (i, buyer) in buyers.iter().enumerate() {
+        if let Some(phone) = &buyer.phone {
+            blocks.entry(Block::Phone(phone)).or_default().push(i);
+        }
+        if !buyer.consignee.is_empty() {
+            blocks
+                .entry(Block::Consignee(&buyer.consignee))
+                .or_default()
+                .push(i);
+        }
+    }
+    let mut pairs = HashSet::new();
+    for block in blocks.values() {
+        for (n, i) in block.iter().enumerate() {
+            for j in block[n + 1..].iter() {
+                pairs.insert((*i.min(j), *i.max(j)));
+            }
+        }
+    }
+    let mut pairs: Vec<(usize, usize)> = pairs.into_iter().collect();
+    pairs.sort_unstable();
+    pairs
+}
+
+// suggest 列出合并后仍疑似同一买家的订单，按相似度从高到低排列；
+// 配置了 auto_merge 时合并相似度足够高、订单状态等满足 key 且没有手动指定不合并的订单，
+// 合并说明记录到 records；只合并两个都还没有被合并掉的订单，避免合并到没有比较过的订单
+pub fn suggest(
+    orders: Vec<Order>,
+    settings: &FuzzySettings,
+    key: &MergeKey,
+    overrides: &Overrides,
+    records: &mut Vec<MergeRecord>,
+) -> (Vec<Order>, Vec<Suggestion>) {
+    let buyers: Vec<Buyer> = orders.iter().map(Buyer::new).collect();
+    // (订单, 疑似订单, 是否满足合并条件, 疑似同一买家)
+    let mut suggestions: Vec<(usize, usize, bool, Suggestion)> = Vec::new();
+    for (i, j) in candidates(&buyers) {
+        let (a, b) = (&buyers[i], &buyers[j]);
+        let consignee_score = similarity(&a.consignee, &b.consignee);
+        let address_score = similarity(&a.address, &b.address);
+        let same_phone = a.phone.is_some() && a.phone == b.phone;
+        let score = CONSIGNEE_WEIGHT * consignee_score
+            + ADDRESS_WEIGHT * address_score
+            + if same_phone { PHONE_WEIGHT } else { 0.0 };
+        if score + f64::EPSILON < settings.suggest {
+            continue;
+        }
+        suggestions.push((
+            i,
+            j,
+            key.compatible(&orders[i], &orders[j]),
+            Suggestion {
+                score: round(score),
+                consignee_score: round(consignee_score),
+                address_score: round(address_score),
+                same_phone,
+                first: SuggestSide::new(&orders[i]),
+                second: SuggestSide::new(&orders[j]),
+                auto_merged: false,
+            },
+        ));
+    }
+    suggestions.sort_by(|a, b| b.3.score.total_cmp(&a.3.score));
+
+    // members 每个订单已经合并进来的订单（按原来的位置）
+    let mut members: Vec<Vec<usize>> = (0..orders.len()).map(|i| vec![i]).collect();
+    let mut orders: Vec<Option<Order>> = orders.into_iter().map(Some).collect();
+    if let Some(threshold) = settings.auto_merge {
+        let mergeable: HashSet<(usize, usize)> = suggestions
+            .iter()
+            .filter(|(.., compatible, s)| *compatible && s.score + f64::EPSILON >= threshold)
+            .map(|(i, j, ..)| (*i, *j))
+            .collect();
+        for (i, j, _, suggestion) in suggestions.iter_mut() {
+            let (root, other) = (*i, *j);
+            if !mergeable.contains(&(root, other)) {
+                continue;
+            }
+            // 两个订单都没有被合并掉，并且双方已合并的订单两两都可以自动合并
+            let (kept, merged) = match (&orders[root], &orders[other]) {
+                (Some(kept), Some(merged)) => (kept, merged),
+                _ => continue,
+            };
+            let linked = members[root].iter().all(|a| {
+                members[other]
+                    .iter()
+                    .all(|b| mergeable.contains(&(*a.min(b), *a.max(b))))
+            });
+            if !linked || !overrides.can_merge(kept.ids(), merged.ids()) {
+                continue;
+            }
+            let absorbed = std::mem::take(&mut members[other]);
+            members[root].extend(absorbed);
+            let merged = orders[other].take().expect("merged order is taken once");
+            let kept = orders[root].as_mut().expect("root order is kept");
+            records.push(MergeRecord {
+                kind: "疑似同一买家",
+                matched: suggestion.matched(key),
+                phone_basis: "",
+                score: Some(suggestion.score),
+                into: MergeSide::new(kept),
+                merged: MergeSide::new(&merged),
+            });
+            kept.merge_diff(&merged);
+            suggestion.auto_merged = true;
+        }
+    }
+
+    let suggestions = suggestions.into_iter().map(|(.., s)| s).collect();
+    (orders.into_iter().flatten().collect(), suggestions)
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    fn order(id: &str, consignee: &str, address: &str, phone: &str) -> Order {
+        let mut order = Order::empty();
+        order.id = String::from(id);
+        order.consignee = String::from(consignee);
+        order.shipping_address = String::from(address);
+        order.phone = String::from(phone);
+        order
+    }
+
+    #[test]
+    fn test_similarity() {
+        assert_eq!(similarity("", ""), 1.0);
+        assert_eq!(similarity("张三", "张三"), 1.0);
+        assert_eq!(similarity("张三", "李四"), 0.0);
+        assert_eq!(similarity("科技园1号", "科技圆1号"), 0.8);
+        assert_eq!(similarity("abc", "abcd"), 0.75);
+    }
+
+    #[test]
+    fn test_suggest() {
+        let orders = vec![
+            order("1", "张三", "深圳市南山区科技园1号", "13800000000"),
+            order("2", "张三 ", "深圳市南山区科技园1号", "13800000000"),
+            order("3", "张三", "深圳市南山区科技圆1号", "13800000000"),
+            order("4", "张三", "广州市天河区体育西路", "13900000000"),
+            order("5", "李四", "深圳市福田区", "13700000000"),
+        ];
+
+        let settings = FuzzySettings::default();
+        let mut records = vec![];
+        let (orders, suggestions) = suggest(
+            orders,
+            &settings,
+            &MergeKey::default(),
+            &Overrides::default(),
+            &mut records,
+        );
+        assert_eq!(orders.len(), 5);
+        assert!(records.is_empty());
+        let pairs: Vec<(&str, &str)> = suggestions
+            .iter()
+            .map(|s| (s.first.id.as_str(), s.second.id.as_str()))
+            .collect();
+        assert_eq!(pairs, vec![("1", "2"), ("1", "3"), ("2", "3")]);
+        assert_eq!(suggestions[0].score, 1.0);
+        assert!(suggestions[1].score < 1.0);
+        assert!(suggestions.iter().all(|s| !s.auto_merged));
+
+        // 自动合并相似度为 1 的订单
+        let settings = FuzzySettings {
+            suggest: 0.8,
+            auto_merge: Some(1.0),
+        };
+        let (orders, suggestions) = suggest(
+            orders,
+            &settings,
+            &MergeKey::default(),
+            &Overrides::default(),
+            &mut records,
+        );
+        assert_eq!(orders.len(), 4);
+        assert_eq!(orders[0].merged, vec![String::from("2")]);
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].kind, "疑似同一买家");
+        assert_eq!(records[0].score, Some(1.0));
+        assert!(suggestions[0].auto_merged);
+        assert!(!suggestions[1].auto_merged);
+    }
+
+    #[test]
+    fn test_suggest_auto_merge_pairs_only() {
+        // 1~2、2~3 都足够相似，1 和 3 不相似，3 不能因为 2 合并到 1
+        let orders = vec![
+            order("1", "张三", "深圳市南山区科技园1号", "13800000000"),
+            order("2", "张三", "深圳市南山区科技园1号", "13900000000"),
+            order("3", "张三丰", "深圳市南山区科技园1号", "13900000000"),
+        ];
+        let settings = FuzzySettings {
+            suggest: 0.7,
+            auto_merge: Some(0.7),
+        };
+        let mut records = vec![];
+        let (orders, suggestions) = suggest(
+            orders,
+            &settings,
+            &MergeKey::default(),
+            &Overrides::default(),
+            &mut records,
+        );
+        assert_eq!(suggestions.len(), 2);
+        assert_eq!(orders.len(), 2);
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].into.id, "2");
+        assert_eq!(records[0].merged.id, "3");
+        assert!(suggestions[0].auto_merged);
+        assert!(!suggestions[1].auto_merged);
+    }
+
+    #[test]
+    fn test_fuzzy_settings_check() {
+        assert!(FuzzySettings::default().check().is_ok());
+        let settings = FuzzySettings {
+            suggest: 0.8,
+            auto_merge: Some(0.7),
+        };
+        assert!(settings.check().is_err());
+    }
+}
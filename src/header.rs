@@ -0,0 +1,198 @@
+use crate::mapping::{ColumnMapping, Field};
+use crate::platform;
+use calamine::{Data, DataType};
+use std::collections::HashMap;
+
+// DEFAULT_SCAN_ROWS 默认在前几行中查找表头
+pub const DEFAULT_SCAN_ROWS: usize = 10;
+
+// Header 识别出的表头
+#[derive(Debug, PartialEq)]
+pub struct Header {
+    pub title_index: HashMap<String, usize>, // 列名 -> 列号
+    pub data_start: usize,                   // 数据从第几行开始
+}
+
+// row_index 读取一行表头
+pub fn row_index(row: &[Data]) -> HashMap<String, usize> {
+    let mut title_index: HashMap<String, usize> = HashMap::new();
+    row.iter().enumerate().for_each(|(i, title)| {
+        if let Some(title) = title.get_string() {
+            title_index.insert(String::from(title), i);
+        }
+    });
+    title_index
+}
+
+// two_row_index 读取两行表头。上一行是分组标题时（合并单元格只有第一格有值），
+// 下一行的每一列以 "分组-标题"、"分组标题" 和 "标题" 三种列名登记；
+// 下一行为空的列是上下合并的单列，使用上一行的标题
+fn two_row_index(upper: &[Data], lower: &[Data]) -> HashMap<String, usize> {
+    let mut title_index: HashMap<String, usize> = HashMap::new();
+    let mut group = String::new();
+    for i in 0..upper.len().max(lower.len()) {
+        let up = upper.get(i).and_then(|v| v.get_string()).map(str::trim);
+        let low = lower.get(i).and_then(|v| v.get_string()).map(str::trim);
+        if let Some(up) = up.filter(|v| !v.is_empty()) {
+            group = String::from(up);
+        }
+        match low.filter(|v| !v.is_empty()) {
+            Some(low) => {
+                if !group.is_empty() {
+                    title_index.insert(format!("{}-{}", group, low), i);
+                    title_index.insert(format!("{}{}", group, low), i);
+                }
+                title_index.entry(String::from(low)).or_insert(i);
+            }
+            None => {
+                if let Some(up) = up.filter(|v| !v.is_empty()) {
+                    title_index.entry(String::from(up)).or_insert(i);
+                }
+                group.clear();
+            }
+        }
+    }
+    title_index
+}
+
+// score 表头能匹配上的字段数，取所有平台中的最大值，不计只用于合并条件的字段
+fn score(title_index: &HashMap<String, usize>, mapping: &ColumnMapping) -> usize {
+    platform::all()
+        .map(|platform| {
+            mapping
+                .resolve(title_index, platform)
+                .keys()
+                .filter(|title| {
+                    Field::from_key(title)
+                        .map(|field| !field.for_merge())
+                        .unwrap_or(true)
+                })
+                .count()
+        })
+        .max()
+        .unwrap_or(0)
+}
+
+// detect 在前 scan_rows 行中查找与已知列名匹配最多的行作为表头，
+// 跳过表头上方的标题、导出时间、店铺名等信息；两行表头匹配更多时使用两行表头。
+// rows 为 sheet 开头的行，多给一行用于判断两行表头
+pub fn detect(
+    rows: &[&[Data]],
+    mapping: &ColumnMapping,
+    scan_rows: usize,
+) -> Result<Header, String> {
+    if rows.is_empty() {
+        return Err(String::from("sheet can not be empty"));
+    }
+
+    let mut best: Option<(usize, Header)> = None;
+    for i in 0..rows.len().min(scan_rows) {
+        let single = row_index(rows[i]);
+        let single_score = score(&single, mapping);
+        let mut candidates = vec![(single_score, single, i + 1)];
+
+        if let Some(lower) = rows.get(i + 1) {
+            let double = two_row_index(rows[i], lower);
+            let lower_score = score(&row_index(lower), mapping);
+            let double_score = score(&double, mapping);
+            if double_score > single_score && double_score > lower_score {
+                candidates.push((double_score, double, i + 2));
+            }
+        }
+
+        for (s, title_index, data_start) in candidates {
+            if best.as_ref().map(|(b, _)| s > *b).unwrap_or(s > 0) {
+                best = Some((
+                    s,
+                    Header {
+                        title_index,
+                        data_start,
+                    },
+                ));
+            }
+        }
+    }
+
+    best.map(|(_, header)| header)
+        .ok_or_else(|| format!("can't find header in first {} rows", scan_rows))
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    fn cells(rows: &[&[&str]]) -> Vec<Vec<Data>> {
+        rows.iter()
+            .map(|row| {
+                row.iter()
+                    .map(|v| {
+                        if v.is_empty() {
+                            Data::Empty
+                        } else {
+                            Data::String(v.to_string())
+                        }
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    fn detect_in(rows: &[Vec<Data>], scan_rows: usize) -> Result<Header, String> {
+        let rows: Vec<&[Data]> = rows.iter().map(Vec::as_slice).collect();
+        detect(&rows, &ColumnMapping::default(), scan_rows)
+    }
+
+    #[test]
+    fn test_row_index() {
+        let row = cells(&[&["", "hello", "world"]]);
+
+        let title_index = row_index(&row[0]);
+        assert_eq!(title_index.len(), 2);
+        assert_eq!(title_index.get("hello").unwrap(), &1);
+        assert_eq!(title_index.get("world").unwrap(), &2);
+    }
+
+    #[test]
+    fn test_detect_after_banner() {
+        let rows = cells(&[
+            &["某某旗舰店订单导出", "", ""],
+            &["导出时间: 2020-10-17 10:00", "", ""],
+            &["订单编号", "收货人姓名", "数量"],
+            &["1001", "张三", "1"],
+        ]);
+        let header = detect_in(&rows, DEFAULT_SCAN_ROWS).unwrap();
+        assert_eq!(header.data_start, 3);
+        assert_eq!(header.title_index["收货人姓名"], 1);
+
+        // 扫描范围内找不到表头
+        assert!(detect_in(&rows, 2).is_err());
+    }
+
+    #[test]
+    fn test_detect_two_row_header() {
+        let rows = cells(&[
+            &["订单编号", "收货信息", "", "", "商品信息", ""],
+            &["", "收货人", "地址", "手机", "货品标题", "数量"],
+            &["1001", "张三", "深圳", "13800000000", "AX199", "1"],
+        ]);
+        let header = detect_in(&rows, DEFAULT_SCAN_ROWS).unwrap();
+        assert_eq!(header.data_start, 2);
+        assert_eq!(header.title_index["订单编号"], 0);
+        assert_eq!(header.title_index["收货信息-收货人"], 1);
+        assert_eq!(header.title_index["收货信息地址"], 2);
+        assert_eq!(header.title_index["手机"], 3);
+        assert_eq!(header.title_index["数量"], 5);
+    }
+
+    #[test]
+    fn test_detect_single_row_header() {
+        let rows = cells(&[
+            &["订单编号", "收货人姓名", "收货地址"],
+            &["1001", "张三", "深圳"],
+        ]);
+        let header = detect_in(&rows, DEFAULT_SCAN_ROWS).unwrap();
+        assert_eq!(header.data_start, 1);
+        assert_eq!(header.title_index.len(), 3);
+    }
+}
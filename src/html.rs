@@ -0,0 +1,294 @@
+use crate::delimited::{self, to_cell};
+use crate::input::{self, Rows};
+use calamine::Data;
+use std::{fs, io::Read, path::Path};
+
+// is_html 部分后台导出的 xls 实际上是 html 表格，根据文件开头的内容识别
+pub fn is_html<P>(path: P) -> bool
+where
+    P: AsRef<Path>,
+{
+    let mut head = [0u8; 1024];
+    let n = match fs::File::open(path).and_then(|mut f| f.read(&mut head)) {
+        Ok(n) => n,
+        Err(_) => return false,
+    };
+    let head = String::from_utf8_lossy(&head[..n]).to_ascii_lowercase();
+    let head = head.trim_start_matches('\u{feff}').trim_start();
+    head.starts_with('<')
+        && (head.contains("<html") || head.contains("<table") || head.contains("<!doctype html"))
+}
+
+// Cell html 表格中的一个单元格
+#[derive(Debug, PartialEq)]
+struct Cell {
+    text: String,
+    rowspan: usize,
+    colspan: usize,
+}
+
+// attr_usize 读取 rowspan/colspan 这类数字属性，缺省为 1
+fn attr_usize(tag: &str, name: &str) -> usize {
+    let lower = tag.to_ascii_lowercase();
+    let pos = match lower.find(name) {
+        Some(pos) => pos + name.len(),
+        None => return 1,
+    };
+    let value = lower[pos..].trim_start();
+    let value = match value.strip_prefix('=') {
+        Some(value) => value.trim_start().trim_start_matches(['"', '\'']),
+        None => return 1,
+    };
+    let digits: String = value.chars().take_while(|c| c.is_ascii_digit()).collect();
+    digits.parse().unwrap_or(1).max(1)
+}
+
+// decode_entities 解码常见的 html 实体
+fn decode_entities(text: &str) -> String {
+    let mut res = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(i) = rest.find('&') {
+        res.push_str(&rest[..i]);
+        rest = &rest[i..];
+        let end = match rest.find(';') {
+            Some(end) if end <= 10 => end,
+            _ => {
+                res.push('&');
+                rest = &rest[1..];
+                continue;
+            }
+        };
+        let entity = &rest[1..end];
+        let decoded = match entity {
+            "nbsp" => Some(' '),
+            "amp" => Some('&'),
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            "mdash" => Some('—'),
+            _ if entity.starts_with("#x") || entity.starts_with("#X") => {
+                u32::from_str_radix(&entity[2..], 16)
+                    .ok()
+                    .and_then(std::char::from_u32)
+            }
+            _ if entity.starts_with('#') => entity[1..]
+                .parse::<u32>()
+                .ok()
+                .and_then(std::char::from_u32),
+            _ => None,
+        };
+        match decoded {
+            Some(c) => {
+                res.push(c);
+                rest = &rest[end + 1..];
+            }
+            None => {
+                res.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    res.push_str(rest);
+    res
+}
+
+// clean_text 合并 html 源码中的空白，<br> 保留为换行
+fn clean_text(raw: &str) -> String {
+    raw.split('\u{0}')
+        .map(|line| {
+            let line = decode_entities(line);
+            line.split_whitespace().collect::<Vec<&str>>().join(" ")
+        })
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
+// parse_cells 解析第一个表格中的所有行，单元格内的其它标签会被忽略
+fn parse_cells(html: &str) -> Vec<Vec<Cell>> {
+    // 只转换 ascii 字符，下标与原文一致
+    let lower = html.to_ascii_lowercase();
+    let mut pos = match lower.find("<table") {
+        Some(pos) => pos,
+        None => return vec![],
+    };
+
+    let mut rows: Vec<Vec<Cell>> = Vec::new();
+    let mut row: Option<Vec<Cell>> = None;
+    let mut cell: Option<Cell> = None;
+
+    fn close_cell(row: &mut Option<Vec<Cell>>, cell: &mut Option<Cell>) {
+        if let Some(mut c) = cell.take() {
+            c.text = clean_text(&c.text);
+            row.get_or_insert_with(Vec::new).push(c);
+        }
+    }
+
+    while let Some(lt) = lower[pos..].find('<').map(|i| pos + i) {
+        if let Some(c) = cell.as_mut() {
+            c.text.push_str(&html[pos..lt]);
+        }
+        if lower[lt..].starts_with("<!--") {
+            pos = lower[lt..]
+                .find("-->")
+                .map(|i| lt + i + 3)
+                .unwrap_or(lower.len());
+            continue;
+        }
+        let gt = match lower[lt..].find('>') {
+            Some(i) => lt + i,
+            None => break,
+        };
+        let tag = &html[lt + 1..gt];
+        let name: String = lower[lt + 1..gt]
+            .trim_end_matches('/')
+            .split_whitespace()
+            .next()
+            .unwrap_or("")
+            .to_string();
+        pos = gt + 1;
+
+        match name.as_str() {
+            "tr" => {
+                close_cell(&mut row, &mut cell);
+                if let Some(r) = row.take() {
+                    rows.push(r);
+                }
+                row = Some(Vec::new());
+            }
+            "/tr" => {
+                close_cell(&mut row, &mut cell);
+                if let Some(r) = row.take() {
+                    rows.push(r);
+                }
+            }
+            "td" | "th" => {
+                close_cell(&mut row, &mut cell);
+                cell = Some(Cell {
+                    text: String::new(),
+                    rowspan: attr_usize(tag, "rowspan"),
+                    colspan: attr_usize(tag, "colspan"),
+                });
+            }
+            "/td" | "/th" => close_cell(&mut row, &mut cell),
+            "br" => {
+                if let Some(c) = cell.as_mut() {
+                    c.text.push('\u{0}');
+                }
+            }
+            "/table" => break,
+            _ => {}
+        }
+    }
+    close_cell(&mut row, &mut cell);
+    if let Some(r) = row.take() {
+        rows.push(r);
+    }
+    rows
+}
+
+// layout 按 rowspan/colspan 将单元格放到网格中，被合并的位置为空，
+// 这样一个订单的多个商品与 xls 导出一样，后续行的订单信息为空
+fn layout(rows: Vec<Vec<Cell>>) -> Vec<Vec<Data>> {
+    let mut spans: Vec<usize> = Vec::new(); // 每列还要被上方单元格占用的行数
+    let mut grid = Vec::new();
+
+    for cells in rows.into_iter() {
+        let mut out = Vec::new();
+        let mut col = 0;
+        for cell in cells.into_iter() {
+            while col < spans.len() && spans[col] > 0 {
+                spans[col] -= 1;
+                out.push(Data::Empty);
+                col += 1;
+            }
+            for k in 0..cell.colspan {
+                out.push(if k == 0 {
+                    to_cell(&cell.text)
+                } else {
+                    Data::Empty
+                });
+                if spans.len() <= col {
+                    spans.resize(col + 1, 0);
+                }
+                spans[col] = cell.rowspan - 1;
+                col += 1;
+            }
+        }
+        while col < spans.len() {
+            if spans[col] > 0 {
+                spans[col] -= 1;
+            }
+            col += 1;
+        }
+        grid.push(out);
+    }
+    grid
+}
+
+// parse 将 html 表格解析为与 xls 一致的单元格
+pub fn parse(html: &str) -> Vec<Vec<Data>> {
+    layout(parse_cells(html))
+}
+
+// read_rows 读取 html 表格，编码识别与 csv 一致；html 需要整个解析完才能处理合并单元格
+pub fn read_rows<P>(path: P) -> Result<Rows, String>
+where
+    P: AsRef<Path>,
+{
+    let bytes = fs::read(path).map_err(|err| format!("can't open file: {}", err))?;
+    let (content, encoding) = delimited::decode(&bytes)?;
+    println!("html table, encoding: {}", encoding.name());
+    Ok(input::vec_rows(parse(&content)))
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use calamine::DataType;
+
+    #[test]
+    fn test_parse_cells() {
+        let rows = parse_cells(
+            r#"<html><body><table border=1>
+            <tr><th>订单编号</th><th>货品标题</th></tr>
+            <tr><td rowspan="2">1001</td><td>AX199&nbsp;红色<br/>110cm</td></tr>
+            <!-- <tr><td>ignored</td></tr> -->
+            <tr><td colspan=2>A &amp; B</td>
+            </table><table><tr><td>other</td></tr></table>"#,
+        );
+        assert_eq!(rows.len(), 3);
+        assert_eq!(rows[0][0].text, "订单编号");
+        assert_eq!(rows[1][0].rowspan, 2);
+        assert_eq!(rows[1][1].text, "AX199 红色\n110cm");
+        assert_eq!(rows[2][0].text, "A & B");
+        assert_eq!(rows[2][0].colspan, 2);
+    }
+
+    #[test]
+    fn test_parse_with_spans() {
+        let rows = parse(
+            "<table>
+            <tr><td>订单编号</td><td>收货人姓名</td><td>货品标题</td><td>数量</td></tr>
+            <tr><td rowspan=2>1001</td><td rowspan=2>张三</td><td>AX199 红</td><td>1</td></tr>
+            <tr><td>AX199 蓝</td><td>2</td></tr>
+            <tr><td>1002</td><td>李四</td><td colspan=2>AX200</td></tr>
+            </table>",
+        );
+        assert_eq!(rows.len(), 4);
+        assert!(rows.iter().all(|row| row.len() == 4));
+        assert_eq!(rows[2][0], Data::Empty);
+        assert_eq!(rows[2][1], Data::Empty);
+        assert_eq!(rows[2][2].get_string(), Some("AX199 蓝"));
+        assert_eq!(rows[2][3].get_string(), Some("2"));
+        assert_eq!(rows[3][0].get_string(), Some("1002"));
+        assert_eq!(rows[3][3], Data::Empty);
+    }
+
+    #[test]
+    fn test_decode_entities() {
+        assert_eq!(decode_entities("a&lt;b&gt;&#21333;&#x53F7;"), "a<b>单号");
+        assert_eq!(decode_entities("AT&T &unknown;"), "AT&T &unknown;");
+    }
+}
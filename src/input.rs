@@ -0,0 +1,259 @@
+use crate::{delimited, html};
+use calamine::{self, Data, Range, Reader, Xlsx};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    sync::mpsc,
+    thread,
+};
+
+// Row sheet 中的一行：从 0 开始的行号与单元格
+pub type Row = (u32, Vec<Data>);
+
+// Rows 逐行读取的 sheet
+pub type Rows = Box<dyn Iterator<Item = Result<Row, String>>>;
+
+// CHANNEL_ROWS 后台读取 xlsx 时最多缓存的行数
+const CHANNEL_ROWS: usize = 1024;
+
+// EXTENSIONS 目录中会被读取的文件类型
+const EXTENSIONS: [&str; 9] = [
+    "xls", "xlsx", "xlsm", "xlsb", "ods", "csv", "tsv", "htm", "html",
+];
+
+fn is_supported(path: &Path) -> bool {
+    let name = path
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default();
+    // 跳过 Excel 打开文件时生成的临时文件
+    if name.starts_with("~$") {
+        return false;
+    }
+    match path.extension() {
+        Some(ext) => EXTENSIONS.contains(&ext.to_string_lossy().to_lowercase().as_str()),
+        None => false,
+    }
+}
+
+// expand 展开输入：目录读取其中支持的文件，含通配符的按 glob 匹配，其它按文件处理
+pub fn expand(inputs: &[String]) -> Result<Vec<PathBuf>, String> {
+    let mut res: Vec<PathBuf> = Vec::new();
+    for input in inputs.iter() {
+        let path = Path::new(input);
+        let mut paths: Vec<PathBuf> = if path.is_dir() {
+            fs::read_dir(path)
+                .map_err(|err| format!("can't read dir {}: {}", input, err))?
+                .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+                .filter(|path| path.is_file() && is_supported(path))
+                .collect()
+        } else if input.contains(['*', '?', '[']) {
+            glob::glob(input)
+                .map_err(|err| format!("invalid pattern {}: {}", input, err))?
+                .filter_map(Result::ok)
+                .filter(|path| path.is_file())
+                .collect()
+        } else {
+            vec![path.to_path_buf()]
+        };
+        if paths.is_empty() {
+            return Err(format!("no file matches {}", input));
+        }
+        paths.sort();
+        for path in paths {
+            // glob 返回的路径不带 "./"，统一去掉以便去重
+            let path = match path.strip_prefix(".") {
+                Ok(stripped) => stripped.to_path_buf(),
+                Err(_) => path,
+            };
+            if !res.contains(&path) {
+                res.push(path);
+            }
+        }
+    }
+    Ok(res)
+}
+
+// sheet_names 文件中要读取的 sheet，all_sheets 为 false 时只读取第一个；
+// html 表格与 csv/tsv 文件只有一个表，sheet 名为空
+pub fn sheet_names<P>(path: P, all_sheets: bool) -> Result<Vec<String>, String>
+where
+    P: AsRef<Path>,
+{
+    if html::is_html(&path) || delimited::is_delimited(&path) {
+        return Ok(vec![String::new()]);
+    }
+
+    let workbook =
+        calamine::open_workbook_auto(path).map_err(|err| format!("can't open file: {}", err))?;
+    let mut names = workbook.sheet_names();
+    if names.is_empty() {
+        return Err(String::from("no sheet"));
+    }
+    if !all_sheets {
+        names.truncate(1);
+    }
+    Ok(names)
+}
+
+fn is_xlsx(path: &Path) -> bool {
+    match path.extension() {
+        Some(ext) => {
+            let ext = ext.to_string_lossy().to_lowercase();
+            ext == "xlsx" || ext == "xlsm"
+        }
+        None => false,
+    }
+}
+
+// read_rows 逐行读取一个 sheet。xlsx 在后台线程中按单元格流式读取，
+// 其它格式 calamine 只能整个读入，xls 最多 65536 行
+pub fn read_rows<P>(path: P, sheet: &str) -> Result<Rows, String>
+where
+    P: AsRef<Path>,
+{
+    if html::is_html(&path) {
+        return html::read_rows(path);
+    }
+    if delimited::is_delimited(&path) {
+        return delimited::read_rows(path);
+    }
+    if is_xlsx(path.as_ref()) {
+        return Ok(xlsx_rows(path.as_ref().to_path_buf(), sheet.to_string()));
+    }
+
+    let mut workbook =
+        calamine::open_workbook_auto(path).map_err(|err| format!("can't open file: {}", err))?;
+    let range = workbook
+        .worksheet_range(sheet)
+        .map_err(|err| format!("read sheet {} failed: {}", sheet, err))?;
+    Ok(range_rows(range))
+}
+
+// vec_rows 已经读出的所有行
+pub fn vec_rows(rows: Vec<Vec<Data>>) -> Rows {
+    Box::new(
+        rows.into_iter()
+            .enumerate()
+            .map(|(i, row)| Ok((i as u32, row))),
+    )
+}
+
+// range_rows 逐行取出 Range 中的单元格，行号为 sheet 中的行号
+fn range_rows(range: Range<Data>) -> Rows {
+    let (first_row, _) = range.start().unwrap_or((0, 0));
+    let (height, width) = range.get_size();
+    Box::new((0..height).map(move |i| {
+        let row = (0..width)
+            .map(|j| range.get((i, j)).cloned().unwrap_or(Data::Empty))
+            .collect();
+        Ok((first_row + i as u32, row))
+    }))
+}
+
+// xlsx_rows 后台线程按单元格读取 xlsx，组成行后通过有界 channel 传回，
+// 内存中只有共享字符串表和 channel 中的行
+fn xlsx_rows(path: PathBuf, sheet: String) -> Rows {
+    let (tx, rx) = mpsc::sync_channel::<Result<Row, String>>(CHANNEL_ROWS);
+    thread::spawn(move || {
+        let send_rows = || -> Result<(), String> {
+            let mut workbook: Xlsx<_> = calamine::open_workbook(&path)
+                .map_err(|err| format!("can't open file: {}", err))?;
+            let mut cells = workbook
+                .worksheet_cells_reader(&sheet)
+                .map_err(|err| format!("read sheet {} failed: {}", sheet, err))?;
+            let mut row: Option<Row> = None;
+            while let Some(cell) = cells
+                .next_cell()
+                .map_err(|err| format!("read sheet {} failed: {}", sheet, err))?
+            {
+                let (i, j) = cell.get_position();
+                if row.as_ref().map(|(r, _)| *r != i).unwrap_or(true) {
+                    if let Some(done) = row.replace((i, Vec::new())) {
+                        if tx.send(Ok(done)).is_err() {
+                            // 读取方已经停止
+                            return Ok(());
+                        }
+                    }
+                }
+                if let Some((_, values)) = row.as_mut() {
+                    let j = j as usize;
+                    if values.len() <= j {
+                        values.resize(j + 1, Data::Empty);
+                    }
+                    values[j] = Data::from(cell.get_value().clone());
+                }
+            }
+            if let Some(done) = row {
+                tx.send(Ok(done)).ok();
+            }
+            Ok(())
+        };
+        if let Err(err) = send_rows() {
+            tx.send(Err(err)).ok();
+        }
+    });
+    Box::new(rx.into_iter())
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn test_expand() {
+        let paths = expand(&[String::from("./testdatas")]).unwrap();
+        assert!(paths.contains(&PathBuf::from("testdatas/src.xls")));
+        assert!(paths.contains(&PathBuf::from("testdatas/src_gbk.csv")));
+
+        let paths = expand(&[
+            String::from("./testdatas/src*.xls"),
+            String::from("./testdatas/src.xls"),
+        ])
+        .unwrap();
+        assert_eq!(
+            paths,
+            vec![
+                PathBuf::from("testdatas/src.xls"),
+                PathBuf::from("testdatas/src_html.xls"),
+            ]
+        );
+
+        assert!(expand(&[String::from("./testdatas/*.xlsb")]).is_err());
+    }
+
+    #[test]
+    fn test_sheet_names() {
+        let names = sheet_names("./testdatas/src.xls", true).unwrap();
+        assert_eq!(names, vec!["1602936688989_127594747"]);
+
+        let names = sheet_names("./testdatas/src_gbk.csv", true).unwrap();
+        assert_eq!(names, vec![""]);
+
+        let names = sheet_names("./testdatas/multi_sheet.xlsx", true).unwrap();
+        assert_eq!(names, vec!["店铺A", "店铺B", "汇总"]);
+        let names = sheet_names("./testdatas/multi_sheet.xlsx", false).unwrap();
+        assert_eq!(names, vec!["店铺A"]);
+    }
+
+    #[test]
+    fn test_read_rows() {
+        // xlsx 流式读取与整个读入的结果一致
+        let streamed: Vec<Row> = read_rows("./testdatas/multi_sheet.xlsx", "店铺B")
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        let mut workbook = calamine::open_workbook_auto("./testdatas/multi_sheet.xlsx").unwrap();
+        let range = workbook.worksheet_range("店铺B").unwrap();
+        let loaded: Vec<Row> = range_rows(range).collect::<Result<_, _>>().unwrap();
+        assert_eq!(streamed.len(), 3);
+        assert_eq!(streamed, loaded);
+
+        assert!(read_rows("./testdatas/multi_sheet.xlsx", "不存在")
+            .unwrap()
+            .next()
+            .unwrap()
+            .is_err());
+    }
+}
@@ -0,0 +1,132 @@
+use glob::{MatchOptions, Pattern};
+use regex::{Regex, RegexBuilder};
+
+// ItemPattern 货号条件，都不区分大小写，需要匹配整个货号：
+// "/AX1\d{2}/" 为正则，含 * ? [ 的为通配符，其它按货号精确匹配
+#[derive(Debug)]
+pub enum ItemPattern {
+    Exact(String),
+    Glob(Pattern),
+    Regex(Regex),
+}
+
+impl ItemPattern {
+    pub fn parse(text: &str) -> Result<ItemPattern, String> {
+        let text = text.trim();
+        if text.is_empty() {
+            return Err(String::from("empty item pattern"));
+        }
+        if text.len() > 1 && text.starts_with('/') && text.ends_with('/') {
+            let re = RegexBuilder::new(&format!("^(?:{})$", &text[1..text.len() - 1]))
+                .case_insensitive(true)
+                .build()
+                .map_err(|err| format!("invalid item regex {}: {}", text, err))?;
+            return Ok(ItemPattern::Regex(re));
+        }
+        if text.contains(['*', '?', '[']) {
+            let pattern = Pattern::new(text)
+                .map_err(|err| format!("invalid item pattern {}: {}", text, err))?;
+            return Ok(ItemPattern::Glob(pattern));
+        }
+        Ok(ItemPattern::Exact(text.to_lowercase()))
+    }
+
+    pub fn matches(&self, item_no: &str) -> bool {
+        match self {
+            ItemPattern::Exact(v) => item_no.to_lowercase() == *v,
+            ItemPattern::Glob(pattern) => pattern.matches_with(
+                item_no,
+                MatchOptions {
+                    case_sensitive: false,
+                    ..MatchOptions::new()
+                },
+            ),
+            ItemPattern::Regex(re) => re.is_match(item_no),
+        }
+    }
+}
+
+// split 按逗号分隔多个条件，正则中的逗号（如 "/AX\d{2,3}/"）不分隔
+fn split(text: &str) -> Vec<String> {
+    let mut res = Vec::new();
+    let mut current = String::new();
+    for c in text.chars() {
+        let token = current.trim();
+        let in_regex = token.starts_with('/') && !(token.len() > 1 && token.ends_with('/'));
+        if (c == ',' || c == '，') && !in_regex {
+            res.push(std::mem::take(&mut current));
+        } else {
+            current.push(c);
+        }
+    }
+    res.push(current);
+    res
+}
+
+// ItemMatcher 多个货号条件，逗号分隔，如 "AX199,AX200"、"AX1*"、"/AX1\d{2}/"
+#[derive(Debug)]
+pub struct ItemMatcher {
+    patterns: Vec<(String, ItemPattern)>, // 原始写法 -> 条件
+}
+
+impl ItemMatcher {
+    pub fn new(text: &str) -> Result<ItemMatcher, String> {
+        let patterns = split(text)
+            .iter()
+            .filter(|v| !v.trim().is_empty())
+            .map(|v| Ok((v.trim().to_string(), ItemPattern::parse(v)?)))
+            .collect::<Result<Vec<_>, String>>()?;
+        if patterns.is_empty() {
+            return Err(String::from("Didn't get a item_no"));
+        }
+        Ok(ItemMatcher { patterns })
+    }
+
+    // find 返回第一个匹配的条件的原始写法
+    pub fn find(&self, item_no: &str) -> Option<&str> {
+        self.patterns
+            .iter()
+            .find(|(_, pattern)| pattern.matches(item_no))
+            .map(|(text, _)| text.as_str())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn test_item_pattern() {
+        let exact = ItemPattern::parse("aX199").unwrap();
+        assert!(exact.matches("AX199"));
+        assert!(!exact.matches("AX1990"));
+
+        let glob = ItemPattern::parse("ax1*").unwrap();
+        assert!(glob.matches("AX199"));
+        assert!(glob.matches("AX1"));
+        assert!(!glob.matches("AX200"));
+
+        let re = ItemPattern::parse(r"/ax(199|200)/").unwrap();
+        assert!(re.matches("AX200"));
+        assert!(!re.matches("AX2001"));
+
+        assert!(ItemPattern::parse("/ax(/").is_err());
+        assert!(ItemPattern::parse(" ").is_err());
+    }
+
+    #[test]
+    fn test_item_matcher() {
+        let matcher = ItemMatcher::new("AX199, AX2*，/BX\\d+/").unwrap();
+        assert_eq!(matcher.find("ax199"), Some("AX199"));
+        assert_eq!(matcher.find("AX201"), Some("AX2*"));
+        assert_eq!(matcher.find("bx12"), Some("/BX\\d+/"));
+        assert_eq!(matcher.find("CX1"), None);
+
+        let matcher = ItemMatcher::new(r"/AX\d{2,3}/,BX1").unwrap();
+        assert_eq!(matcher.find("AX12"), Some(r"/AX\d{2,3}/"));
+        assert_eq!(matcher.find("bx1"), Some("BX1"));
+
+        assert!(ItemMatcher::new(",").is_err());
+    }
+}
@@ -1,166 +1,1220 @@
-use calamine::{self, DataType, Range, Reader};
+use calamine::Data;
 use chrono::Local;
-use simple_excel_writer::Workbook;
-use std::{collections::HashMap, env, path::Path};
+use std::{
+    collections::HashMap,
+    env,
+    fs::File,
+    io::{BufWriter, Write},
+    path::{Path, PathBuf},
+};
 
+mod address;
+mod delimited;
+mod diagnostic;
+mod fuzzy;
+mod header;
+mod html;
+mod input;
+mod item;
+mod mapping;
+mod money;
 mod opr;
 mod order;
+mod overrides;
+mod phone;
+mod platform;
+mod settings;
+mod sku;
+mod xlsx;
+use diagnostic::Diagnostic;
+use item::ItemMatcher;
+use mapping::{ColumnMapping, Field};
 use order::Order;
-
-fn build_index(range: &Range<DataType>) -> Result<HashMap<String, usize>, String> {
-    let mut title_index: HashMap<String, usize> = HashMap::new();
-    let first_row = range
-        .rows()
-        .nth(0)
-        .ok_or(String::from("first sheet can not be empty"))?;
-    first_row.iter().enumerate().for_each(|(i, title)| {
-        if let Some(title) = title.get_string() {
-            title_index.insert(String::from(title), i);
-        }
-    });
-    Ok(title_index)
-}
+use overrides::Overrides;
+use platform::Platform;
+use settings::Settings;
+use sku::SkuParser;
 
 struct Config {
-    src_path: String,
-    item_no: String,
+    src_paths: Vec<String>,         // 文件、目录或通配符，可以有多个
+    item_no: String,                // 货号条件，逗号分隔，支持通配符和正则
+    config_path: Option<String>,    // 配置文件路径
+    platform: Option<String>,       // 指定导出平台，不指定时自动识别
+    strict: bool,                   // 严格模式，必填字段缺失时不输出
+    all_sheets: bool,               // 读取所有 sheet，默认只读第一个
+    per_item: bool,                 // 每个货号输出一个文件
+    ignore_status: bool,            // 合并同一买家时不比较订单状态
+    merge_with: Vec<String>,        // 合并同一买家时还需要相同的字段
+    overrides_path: Option<String>, // 手动指定合并、不合并、排除订单的文件
 }
 
+// USAGE 命令行用法，参数有误时提示
+const USAGE: &str = "usage: qlion [--strict] [--all-sheets] [--per-item] [--ignore-status] \
+[--merge-with fields] [--config path] [--overrides path] [--platform name] <src_path>... <item_no>";
+
 impl Config {
-    pub fn new(mut args: env::Args) -> Result<Config, String> {
+    pub fn new<I>(mut args: I) -> Result<Config, String>
+    where
+        I: Iterator<Item = String>,
+    {
         args.next();
 
-        let src_path = match args.next() {
-            Some(arg) => arg,
-            None => return Err(String::from("Didn't get a src_path")),
-        };
+        let mut positional = Vec::new();
+        let mut config_path = None;
+        let mut platform = None;
+        let mut strict = false;
+        let mut all_sheets = false;
+        let mut per_item = false;
+        let mut ignore_status = false;
+        let mut merge_with = Vec::new();
+        let mut overrides_path = None;
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--strict" => strict = true,
+                "--all-sheets" => all_sheets = true,
+                "--per-item" => per_item = true,
+                "--ignore-status" => ignore_status = true,
+                "--merge-with" => match args.next() {
+                    Some(fields) => merge_with.extend(fields.split(',').map(String::from)),
+                    None => return Err(String::from("Didn't get merge fields")),
+                },
+                "--config" => match args.next() {
+                    Some(path) => config_path = Some(path),
+                    None => return Err(String::from("Didn't get a config path")),
+                },
+                "--overrides" => match args.next() {
+                    Some(path) => overrides_path = Some(path),
+                    None => return Err(String::from("Didn't get a overrides path")),
+                },
+                "--platform" => match args.next() {
+                    Some(name) => platform = Some(name),
+                    None => return Err(String::from("Didn't get a platform")),
+                },
+                // 拼错的选项不当作输入或货号
+                _ if arg.starts_with("--") => {
+                    return Err(format!("Unknown option {}, {}", arg, USAGE))
+                }
+                _ => positional.push(arg),
+            }
+        }
 
-        let item_no = match args.next() {
-            Some(arg) => arg,
-            None => return Err(String::from("Didn't get a item_no")),
+        // 最后一个参数是货号，前面的都是输入
+        let item_no = match positional.pop() {
+            Some(arg) if !positional.is_empty() => arg,
+            Some(_) => return Err(String::from("Didn't get a item_no")),
+            None => return Err(String::from("Didn't get a src_path")),
         };
 
-        Ok(Config { src_path, item_no })
+        Ok(Config {
+            src_paths: positional,
+            item_no,
+            config_path,
+            platform,
+            strict,
+            all_sheets,
+            per_item,
+            ignore_status,
+            merge_with,
+            overrides_path,
+        })
     }
 }
 
-fn generate_dst_path(item_no: &String, orders: &Vec<Order>) -> String {
+// NAME_ITEM_NOS 文件名中最多列出的货号个数，更多时记为“等N款”，避免文件名超过长度限制
+const NAME_ITEM_NOS: usize = 3;
+
+// generate_dst_path 文件名中包含匹配上的货号，没有订单时使用货号条件
+fn generate_dst_path(item_no: &str, orders: &[Order]) -> String {
+    let mut item_nos: Vec<String> = orders
+        .iter()
+        .flat_map(|order| order.sku.item_no.split('\n'))
+        .filter(|v| !v.is_empty())
+        .map(|v| v.to_uppercase())
+        .collect();
+    item_nos.sort();
+    item_nos.dedup();
+    let item_no = if item_nos.is_empty() {
+        item_no.to_uppercase()
+    } else if item_nos.len() > NAME_ITEM_NOS {
+        format!(
+            "{}等{}款",
+            item_nos[..NAME_ITEM_NOS].join("_"),
+            item_nos.len()
+        )
+    } else {
+        item_nos.join("_")
+    };
+    // 文件名中不能有通配符等字符
+    let item_no = item_no.replace(['*', '?', '/', '\\', ':', '"', '<', '>', '|'], "_");
+
     let mut name = "";
     let mut count = 0;
     orders.iter().for_each(|order| {
         count += order.total_count;
-        if name == "" {
+        if name.is_empty() {
             if let Some(x) = orders[0].item_name.split(' ').nth(1) {
                 name = x;
             }
         }
     });
-    return format!(
+    format!(
         "{}{} {}{}ida.xlsx",
         Local::now().format("%Y%m%d"),
-        item_no.to_uppercase(),
+        item_no,
         name,
         count
-    );
+    )
 }
 
 pub fn work() -> Result<(), String> {
     let config = Config::new(env::args())?;
+    let settings = Settings::load_or_default(config.config_path.as_deref())?;
+    let mapping = ColumnMapping::new(&settings.columns)?;
+    let sku = SkuParser::new(&settings.sku)?;
+    // 命令行的合并条件加在配置文件之上
+    let mut merge_settings = settings.merge.clone();
+    merge_settings.ignore_status |= config.ignore_status;
+    merge_settings
+        .extra
+        .extend(config.merge_with.iter().cloned());
+    let merge_key = opr::MergeKey::new(&merge_settings)?;
+    let overrides = match &config.overrides_path {
+        Some(path) => Overrides::load(path)?,
+        None => Overrides::default(),
+    };
+    let items = ItemMatcher::new(&config.item_no)?;
+    let platform = match &config.platform {
+        Some(name) => Some(platform::find(name)?),
+        None => None,
+    };
 
-    let orders = read_orders(config.src_path)?;
-    println!("read orderes finished, order count: {}", orders.len());
-    let orders = opr::remove_repeat(orders);
-    println!("order count after removing repeat: {}", orders.len());
+    let options = ReadOptions {
+        mapping: &mapping,
+        sku: &sku,
+        platform,
+        header_scan_rows: settings
+            .header_scan_rows
+            .unwrap_or(header::DEFAULT_SCAN_ROWS),
+        all_sheets: config.all_sheets,
+        overrides: Some(&overrides),
+        merge_fields: merge_key.extra(),
+    };
+    let merge_options = MergeOptions {
+        key: &merge_key,
+        overrides: &overrides,
+        fuzzy: &settings.fuzzy,
+    };
 
-    let orders = opr::remove_invalid_item(orders, &config.item_no);
+    let paths = input::expand(&config.src_paths)?;
+    let mut diagnostics = Vec::new();
+    let ReadResult {
+        orders,
+        split_lines,
+        mut duplicates,
+        read,
+        unique,
+        excluded,
+    } = read_all(&paths, &options, &items, &mut diagnostics)?;
+    println!("read orderes finished, order count: {}", read);
+    diagnostics.iter().for_each(|d| println!("{}", d));
+    if config.strict {
+        diagnostic::check_strict(&diagnostics)?;
+    }
+    println!("order count after removing repeat: {}", unique);
+    if excluded > 0 {
+        println!("line count excluded by overrides: {}", excluded);
+    }
+    // 需要人工核对的重复订单排在前面
+    duplicates.sort_by_key(|d| !d.conflict());
+    duplicates
+        .iter()
+        .filter(|d| d.conflict())
+        .for_each(|d| println!("conflicting duplicate: {} {}", d.order.id, d.diff.join(",")));
     println!("order count after removing ivalid: {}", orders.len());
+    println!("split line count: {}", split_lines.len());
+    let report = Report {
+        split_lines: &split_lines,
+        duplicates: &duplicates,
+        diagnostics: &diagnostics,
+    };
+
+    // 按货号分开时，跨货号的订单在每个文件中都标记为已拆
+    let batches = if config.per_item {
+        opr::split_by_item(orders)
+    } else {
+        vec![(config.item_no.clone(), orders)]
+    };
+    for (item_no, orders) in batches {
+        if config.per_item {
+            println!("item {}, order count: {}", item_no, orders.len());
+        }
+        let merged = merge_orders(orders, &merge_options);
+        let path = generate_dst_path(&item_no, &merged.orders);
+        save_orders_to_xlsx(&path, &merged, &report)?;
+        println!("save order finished: {}", path);
+        if !merged.records.is_empty() {
+            let path = Path::new(&path).with_extension("jsonl");
+            save_merge_records(&path, &merged.records)?;
+            println!("save merge records finished: {}", path.display());
+        }
+    }
+
+    Ok(())
+}
 
-    let orders = opr::merge_same_order(orders);
+// MergeResult 合并后的订单
+#[derive(Debug)]
+struct MergeResult {
+    orders: Vec<Order>,                  // 合并后的订单
+    records: Vec<opr::MergeRecord>,      // 合并说明
+    suggestions: Vec<fuzzy::Suggestion>, // 疑似同一买家
+}
+
+// MergeOptions 合并订单的选项
+struct MergeOptions<'a> {
+    key: &'a opr::MergeKey,          // 合并同一买家时比较的字段
+    overrides: &'a Overrides,        // 手动指定合并、不合并的订单
+    fuzzy: &'a fuzzy::FuzzySettings, // 疑似同一买家的阈值
+}
+
+// merge_orders 合并同一订单的商品与同一买家的订单，再合并手动指定的订单，
+// 列出疑似同一买家的订单，并标记同号码的订单
+fn merge_orders(orders: Vec<Order>, options: &MergeOptions) -> MergeResult {
+    let mut records = Vec::new();
+    let orders = opr::merge_same_order(orders, &mut records);
     println!("merge same orderes finished, order count: {}", orders.len(),);
 
-    let mut orders = opr::merge_diff_order(orders);
+    let orders = opr::merge_diff_order(orders, options.key, options.overrides, &mut records);
     println!(
         "merge different orderes finished, order count: {}",
         orders.len(),
     );
 
+    let orders = opr::force_merge(orders, options.overrides, &mut records);
+    println!("force merge finished, order count: {}", orders.len());
+
+    let (mut orders, suggestions) = fuzzy::suggest(
+        orders,
+        options.fuzzy,
+        options.key,
+        options.overrides,
+        &mut records,
+    );
+    println!(
+        "fuzzy match finished, suggestion count: {}, order count: {}",
+        suggestions.len(),
+        orders.len(),
+    );
+
     opr::mark_same_phone_order(&mut orders);
     println!("mark same phone order finished");
+    MergeResult {
+        orders,
+        records,
+        suggestions,
+    }
+}
 
-    save_orders_to_xlsx(&generate_dst_path(&config.item_no, &orders), &orders)?;
-    println!("save order finished");
+// ReadOptions 读取订单的选项
+struct ReadOptions<'a> {
+    mapping: &'a ColumnMapping,
+    sku: &'a SkuParser,
+    platform: Option<&'a dyn Platform>, // 为空时根据表头自动识别导出平台
+    header_scan_rows: usize,            // 在前几行中查找表头
+    all_sheets: bool,                   // 读取所有 sheet
+    overrides: Option<&'a Overrides>,   // 手动指定排除的订单
+    merge_fields: &'a [Field],          // 合并时额外比较的字段，缺少这些列时记录诊断信息
+}
 
-    Ok(())
+impl<'a> ReadOptions<'a> {
+    #[cfg(test)]
+    fn new(mapping: &'a ColumnMapping, sku: &'a SkuParser) -> ReadOptions<'a> {
+        ReadOptions {
+            mapping,
+            sku,
+            platform: None,
+            header_scan_rows: header::DEFAULT_SCAN_ROWS,
+            all_sheets: false,
+            overrides: None,
+            merge_fields: &[],
+        }
+    }
+}
+
+// ReadResult 读取、去重、过滤商品后的结果
+#[derive(Debug, Default)]
+struct ReadResult {
+    orders: Vec<Order>,               // 条件商品的订单
+    split_lines: Vec<opr::SplitLine>, // 拆单移除的商品
+    duplicates: Vec<opr::Duplicate>,  // 重复导出被删除的行
+    read: usize,                      // 读取的订单数
+    unique: usize,                    // 去重后的订单数
+    excluded: usize,                  // 手动指定排除的行数
+}
+
+// read_all 读取所有文件，边读边去重、过滤商品，内存中只保留条件商品的订单
+fn read_all(
+    paths: &[PathBuf],
+    options: &ReadOptions,
+    items: &ItemMatcher,
+    diagnostics: &mut Vec<Diagnostic>,
+) -> Result<ReadResult, String> {
+    let mut res = ReadResult::default();
+    let mut offset = 0;
+    let mut remove_repeat = opr::RemoveRepeat::default();
+    let mut item_filter = opr::ItemFilter::new(items);
+    for path in paths.iter() {
+        println!("read {}", path.display());
+        read_orders(path, options, diagnostics, &mut offset, &mut |order| {
+            res.read += 1;
+            if let Some(order) = remove_repeat.keep(order) {
+                res.unique += 1;
+                if options.overrides.is_some_and(|o| o.excluded(&order.id)) {
+                    res.excluded += 1;
+                    return;
+                }
+                res.orders.extend(item_filter.push(order));
+            }
+        })
+        .map_err(|err| format!("{}: {}", path.display(), err))?;
+    }
+    res.orders.extend(item_filter.finish());
+    res.split_lines = item_filter.split_lines;
+    remove_repeat.finish();
+    res.duplicates = remove_repeat.duplicates;
+    Ok(res)
 }
 
-fn read_orders<P>(path: P) -> Result<Vec<Order>, String>
+// read_orders 逐行读取一个文件中的订单交给 sink，读取所有 sheet 时跳过找不到表头的 sheet。
+// group 加上 offset，保证不同文件、sheet 的订单不会被当作同一组，读完后 offset 为最大的 group
+fn read_orders<P>(
+    path: P,
+    options: &ReadOptions,
+    diagnostics: &mut Vec<Diagnostic>,
+    offset: &mut u32,
+    sink: &mut dyn FnMut(Order),
+) -> Result<(), String>
 where
     P: AsRef<Path>,
 {
-    let mut workbook =
-        calamine::open_workbook_auto(path).map_err(|err| format!("can't open file: {}", err))?;
-    let range = match workbook
-        .worksheet_range_at(0)
-        .ok_or(String::from("no sheet"))?
-    {
-        Ok(range) => range,
-        Err(e) => return Err(format!("{}", e)),
-    };
+    let file = path.as_ref().display().to_string();
+    for sheet in input::sheet_names(&path, options.all_sheets)? {
+        let rows = input::read_rows(&path, &sheet)?;
+        let start = diagnostics.len();
+        let base = *offset;
+        let mut count = 0;
+        let res = read_sheet(rows, options, diagnostics, &mut |mut order| {
+            order.group += base;
+            order.source.file = file.clone();
+            order.source.sheet = sheet.clone();
+            *offset = (*offset).max(order.group);
+            count += 1;
+            sink(order);
+        });
+        match res {
+            Ok(()) => {}
+            Err(err) if options.all_sheets && count == 0 => {
+                println!("skip sheet {}: {}", sheet, err);
+                diagnostics.truncate(start);
+                continue;
+            }
+            Err(err) => return Err(err),
+        }
+        diagnostics[start..].iter_mut().for_each(|d| {
+            d.file = file.clone();
+            d.sheet = sheet.clone();
+        });
+    }
+    Ok(())
+}
 
-    let title_index = &build_index(&range)?;
+// read_sheet 逐行读取一个 sheet 中的订单，只缓存用于识别表头的前几行和上一个订单
+fn read_sheet(
+    mut rows: input::Rows,
+    options: &ReadOptions,
+    diagnostics: &mut Vec<Diagnostic>,
+    sink: &mut dyn FnMut(Order),
+) -> Result<(), String> {
+    let head: Vec<input::Row> = rows
+        .by_ref()
+        .take(options.header_scan_rows + 1)
+        .collect::<Result<_, _>>()?;
+    let head_cells: Vec<&[Data]> = head.iter().map(|(_, row)| row.as_slice()).collect();
+    let header = header::detect(&head_cells, options.mapping, options.header_scan_rows)?;
+    let raw_index = header.title_index;
+    let platform = options
+        .platform
+        .unwrap_or_else(|| platform::detect(&raw_index));
+    println!("platform: {}", platform.name());
 
-    let mut res: Vec<Order> = Vec::new();
-    range.rows().skip(1).enumerate().for_each(|(i, item)| {
-        res.push(Order::from_row(
-            item,
-            title_index,
-            res.last().unwrap_or(&Order::empty()),
-            (i + 1) as u32,
-        ));
-    });
+    // 平台处理特殊情况时还需要用到原始列名，标准列名优先
+    let resolved = options.mapping.resolve(&raw_index, platform);
+    let missing: Vec<&Field> = Field::ALL
+        .iter()
+        .filter(|field| !resolved.contains_key(field.title()))
+        .filter(|field| !field.for_merge() || options.merge_fields.contains(field))
+        .collect();
+    missing
+        .iter()
+        .for_each(|field| println!("column not found: {}", field.title()));
+    // 缺少合并条件的列时所有订单的该字段都为空，这个条件不起作用
+    let header_row = header.data_start as u32;
+    missing
+        .iter()
+        .filter(|field| options.merge_fields.contains(field))
+        .for_each(|field| {
+            diagnostics.push(Diagnostic {
+                file: String::new(),
+                sheet: String::new(),
+                row: header_row,
+                column: String::from(field.title()),
+                raw: String::from("<缺少列>"),
+                message: String::from("合并条件的列不存在，不能按此字段区分订单"),
+                required: true,
+            })
+        });
+    let mut title_index: HashMap<String, usize> = raw_index
+        .into_iter()
+        .map(|(title, i)| (title.trim().to_string(), i))
+        .collect();
+    title_index.extend(resolved);
 
-    Ok(res)
+    // row_index 为 sheet 中从 0 开始的行号；上一个订单读完下一行才交给 sink
+    let mut last_order: Option<Order> = None;
+    // rejected 上一个订单编号无法读取，其后订单编号为空的行都属于这个被跳过的订单
+    let mut rejected = false;
+    let rest = head.into_iter().skip(header.data_start).map(Ok).chain(rows);
+    for row in rest {
+        let (row_index, item) = row?;
+        if rejected && matches!(order::get_id(&item, &title_index, "订单编号"), Ok(None)) {
+            diagnostics.push(Diagnostic {
+                file: String::new(),
+                sheet: String::new(),
+                row: row_index + 1,
+                column: String::from("订单编号"),
+                raw: order::get_raw(&item, &title_index, "订单编号"),
+                message: String::from("属于上面订单编号无法读取的订单，跳过这一行"),
+                required: true,
+            });
+            continue;
+        }
+        rejected = false;
+        let mut order = match Order::from_row(
+            &item,
+            &title_index,
+            last_order.as_ref().unwrap_or(&Order::empty()),
+            row_index,
+            diagnostics,
+        ) {
+            Ok(order) => order,
+            // 订单编号无法读取时跳过这一行，记为必填字段错误，--strict 时拒绝输出
+            Err(err) => {
+                diagnostics.push(Diagnostic {
+                    file: String::new(),
+                    sheet: String::new(),
+                    row: row_index + 1,
+                    column: String::from("订单编号"),
+                    raw: order::get_raw(&item, &title_index, "订单编号"),
+                    message: format!("{}，跳过这一行", err),
+                    required: true,
+                });
+                rejected = true;
+                continue;
+            }
+        };
+        platform.fix(&mut order, &item, &title_index);
+        order.set_sku(options.sku.parse(&order.item_name));
+        if let Some(last_order) = last_order.as_ref() {
+            if platform.id_per_item() && order.id == last_order.id {
+                order.group = last_order.group;
+            }
+        }
+        if let Some(last_order) = last_order.replace(order) {
+            sink(last_order);
+        }
+    }
+    if let Some(last_order) = last_order {
+        sink(last_order);
+    }
+
+    Ok(())
 }
 
-// save_orders_to_xlsx 保存订单到 xlsx 文件
-fn save_orders_to_xlsx(path: &str, orders: &Vec<Order>) -> Result<(), String> {
-    let mut wb = Workbook::create(path);
-    let mut sheet = wb.create_sheet("default");
+// Report 与订单一起输出的其它 sheet
+struct Report<'a> {
+    split_lines: &'a [opr::SplitLine], // 拆单移除的商品
+    duplicates: &'a [opr::Duplicate],  // 重复导出被删除的行
+    diagnostics: &'a [Diagnostic],     // 字段缺失或格式不对
+}
 
-    wb.write_sheet(&mut sheet, |sheet_writer| {
-        let sw = sheet_writer;
-        sw.append_row(Order::excel_title_row())?;
-        for order in orders.iter() {
-            sw.append_row(order.as_excel_row())?;
-        }
-        Ok(())
-    })
-    .map_err(|err| format!("write order failed: {}", err))?;
+// save_orders_to_xlsx 保存订单到 xlsx 文件，订单按手动处理列的标记整行着色，颜色含义见图例 sheet；
+// 这些订单有拆出的商品时另存一个拆出商品 sheet，有合并时另存一个合并说明 sheet，
+// 有疑似同一买家时另存一个疑似同一买家 sheet，有重复导出的行时另存一个重复订单 sheet
+// （需人工核对的行着色），有诊断信息时另存一个校验报告 sheet
+fn save_orders_to_xlsx(path: &str, merged: &MergeResult, report: &Report) -> Result<(), String> {
+    let orders = &merged.orders;
+    let mut wb = rust_xlsxwriter::Workbook::new();
+    let plain = |rows: Vec<xlsx::Row>| rows.into_iter().map(|row| (row, None)).collect();
+
+    let rows = orders
+        .iter()
+        .map(|order| (order.as_excel_row(), xlsx::highlight(&order.flag())))
+        .collect();
+    xlsx::write_sheet(&mut wb, "default", Order::excel_title_row(), rows)
+        .map_err(|err| format!("write order failed: {}", err))?;
+    xlsx::write_legend(&mut wb).map_err(|err| format!("write legend failed: {}", err))?;
+
+    // 订单编号 -> 合并后所在的订单
+    let mut merged_into = HashMap::<&str, &str>::new();
+    orders.iter().for_each(|order| {
+        merged_into.insert(&order.id, &order.id);
+        order.merged.iter().for_each(|id| {
+            merged_into.insert(id, &order.id);
+        });
+    });
+    let split_lines: Vec<xlsx::Row> = report
+        .split_lines
+        .iter()
+        .filter_map(|line| {
+            let merged_into = merged_into.get(line.order.id.as_str())?;
+            Some(line.as_excel_row(merged_into))
+        })
+        .collect();
+    if !split_lines.is_empty() {
+        let title = opr::SplitLine::excel_title_row();
+        xlsx::write_sheet(&mut wb, "拆出商品", title, plain(split_lines))
+            .map_err(|err| format!("write split lines failed: {}", err))?;
+    }
 
-    wb.close()
-        .map(|_| ())
+    if !merged.records.is_empty() {
+        let rows = merged.records.iter().map(|r| r.as_excel_row()).collect();
+        let title = opr::MergeRecord::excel_title_row();
+        xlsx::write_sheet(&mut wb, "合并说明", title, plain(rows))
+            .map_err(|err| format!("write merge records failed: {}", err))?;
+    }
+
+    if !merged.suggestions.is_empty() {
+        let rows = merged
+            .suggestions
+            .iter()
+            .map(|s| s.as_excel_row())
+            .collect();
+        let title = fuzzy::Suggestion::excel_title_row();
+        xlsx::write_sheet(&mut wb, "疑似同一买家", title, plain(rows))
+            .map_err(|err| format!("write suggestions failed: {}", err))?;
+    }
+
+    if !report.duplicates.is_empty() {
+        let rows = report
+            .duplicates
+            .iter()
+            .map(|d| (d.as_excel_row(), d.conflict().then_some(xlsx::CONFLICT)))
+            .collect();
+        let title = opr::Duplicate::excel_title_row();
+        xlsx::write_sheet(&mut wb, "重复订单", title, rows)
+            .map_err(|err| format!("write duplicates failed: {}", err))?;
+    }
+
+    if !report.diagnostics.is_empty() {
+        let rows = report
+            .diagnostics
+            .iter()
+            .map(|d| d.as_excel_row())
+            .collect();
+        xlsx::write_sheet(
+            &mut wb,
+            "校验报告",
+            Diagnostic::excel_title_row(),
+            plain(rows),
+        )
+        .map_err(|err| format!("write diagnostics failed: {}", err))?;
+    }
+
+    wb.save(path)
         .map_err(|err| format!("close dst file failed: {}", err))
 }
 
+// save_merge_records 保存合并说明，每行一个 json
+fn save_merge_records(path: &Path, records: &[opr::MergeRecord]) -> Result<(), String> {
+    let file =
+        File::create(path).map_err(|err| format!("create {} failed: {}", path.display(), err))?;
+    let mut writer = BufWriter::new(file);
+    for record in records.iter() {
+        serde_json::to_writer(&mut writer, record)
+            .map_err(|err| format!("write merge record failed: {}", err))?;
+        writeln!(writer).map_err(|err| format!("write merge record failed: {}", err))?;
+    }
+    writer
+        .flush()
+        .map_err(|err| format!("write merge record failed: {}", err))
+}
+
 #[cfg(test)]
 mod tests {
 
     use super::*;
 
+    fn read(
+        path: &str,
+        options: &ReadOptions,
+        diagnostics: &mut Vec<Diagnostic>,
+    ) -> Result<Vec<Order>, String> {
+        let mut orders = Vec::new();
+        read_orders(path, options, diagnostics, &mut 0, &mut |order| {
+            orders.push(order)
+        })?;
+        Ok(orders)
+    }
+
+    #[test]
+    fn test_read() {
+        let orders = read(
+            "./testdatas/src.xls",
+            &ReadOptions::new(&ColumnMapping::default(), &SkuParser::default()),
+            &mut vec![],
+        )
+        .expect("read orders failed");
+        assert_eq!(orders[0].id, "1232693822339834520");
+        assert_eq!(orders[0].consignee, "刘萌萌");
+    }
+
+    #[test]
+    fn test_read_orders_diagnostics() {
+        let mut diagnostics = vec![];
+        read(
+            "./testdatas/src_gbk.csv",
+            &ReadOptions::new(&ColumnMapping::default(), &SkuParser::default()),
+            &mut diagnostics,
+        )
+        .expect("read orders failed");
+        // 联系电话为空但有手机号，不算必填字段缺失
+        assert!(diagnostic::check_strict(&diagnostics).is_ok());
+
+        let mut diagnostics = vec![];
+        read(
+            "./testdatas/src_html.xls",
+            &ReadOptions::new(&ColumnMapping::default(), &SkuParser::default()),
+            &mut diagnostics,
+        )
+        .expect("read orders failed");
+        assert!(diagnostic::check_strict(&diagnostics).is_ok());
+        assert!(diagnostics.iter().all(|d| d.column != "联系手机"));
+
+        // 按店铺合并但导出中没有店铺列
+        let mut diagnostics = vec![];
+        let mapping = ColumnMapping::default();
+        let sku = SkuParser::default();
+        let options = ReadOptions {
+            merge_fields: &[Field::Shop],
+            ..ReadOptions::new(&mapping, &sku)
+        };
+        read("./testdatas/src_gbk.csv", &options, &mut diagnostics).expect("read orders failed");
+        let missing: Vec<&Diagnostic> = diagnostics
+            .iter()
+            .filter(|d| d.column == "店铺名称")
+            .collect();
+        assert_eq!(missing.len(), 1);
+        assert!(missing[0].required);
+        assert!(diagnostic::check_strict(&diagnostics).is_err());
+    }
+
+    #[test]
+    fn test_read_sheet_bad_id() {
+        let title = ["订单编号", "收货人姓名", "货品标题", "数量"];
+        let rows: Vec<Result<input::Row, String>> = vec![
+            (
+                0,
+                title.iter().map(|v| Data::String(v.to_string())).collect(),
+            ),
+            (
+                1,
+                vec![
+                    Data::Int(1001),
+                    "张三".into(),
+                    "AX199 * 1".into(),
+                    1.0.into(),
+                ],
+            ),
+            (
+                2,
+                vec![
+                    Data::Float(12.5),
+                    "李四".into(),
+                    "AX199 * 1".into(),
+                    1.0.into(),
+                ],
+            ),
+            (
+                3,
+                vec![Data::Empty, Data::Empty, "AX299 * 2".into(), 2.0.into()],
+            ),
+            (
+                4,
+                vec![
+                    Data::Int(1003),
+                    "王五".into(),
+                    "AX199 * 1".into(),
+                    1.0.into(),
+                ],
+            ),
+        ]
+        .into_iter()
+        .map(Ok)
+        .collect();
+        let mapping = ColumnMapping::default();
+        let sku = SkuParser::default();
+        let mut diagnostics = vec![];
+        let mut orders = vec![];
+        read_sheet(
+            Box::new(rows.into_iter()),
+            &ReadOptions::new(&mapping, &sku),
+            &mut diagnostics,
+            &mut |order| orders.push(order),
+        )
+        .unwrap();
+
+        let ids: Vec<&str> = orders.iter().map(|order| order.id.as_str()).collect();
+        assert_eq!(ids, vec!["1001", "1003"]);
+        // 被跳过订单的后续行没有并入上一个订单
+        assert_eq!(orders[0].total_count, 1);
+        assert!(!orders[0].item_name.contains("AX299"));
+        let bad: Vec<&Diagnostic> = diagnostics
+            .iter()
+            .filter(|d| d.column == "订单编号")
+            .collect();
+        assert_eq!(bad.len(), 2);
+        assert_eq!(bad[0].row, 3);
+        assert_eq!(bad[0].raw, "12.5");
+        assert_eq!(bad[1].row, 4);
+        assert!(bad.iter().all(|d| d.required));
+        assert!(diagnostic::check_strict(&diagnostics).is_err());
+    }
+
+    #[test]
+    fn test_read_orders_from_gbk_csv() {
+        let orders = read(
+            "./testdatas/src_gbk.csv",
+            &ReadOptions::new(&ColumnMapping::default(), &SkuParser::default()),
+            &mut vec![],
+        )
+        .expect("read orders failed");
+        assert_eq!(orders.len(), 3);
+        assert_eq!(orders[0].id, "1232693822339834520");
+        assert_eq!(orders[0].consignee, "刘萌萌");
+        assert_eq!(orders[0].total_count, 2);
+        assert_eq!(
+            orders[0].item_name,
+            "AX199 20色秋衣套装 颜色: 胡萝卜 适合身高: 110cm * 2"
+        );
+        assert_eq!(orders[0].sku.item_no, "AX199");
+        assert_eq!(orders[0].sku.color, "胡萝卜");
+        assert_eq!(orders[0].sku.size, "110cm");
+        // 订单编号为空的行属于上一个订单
+        assert_eq!(orders[1].id, orders[0].id);
+        assert_eq!(orders[1].group, orders[0].group);
+        assert_eq!(orders[2].consignee, "邓梦婷");
+    }
+
+    #[test]
+    fn test_read_orders_from_html_xls() {
+        let orders = read(
+            "./testdatas/src_html.xls",
+            &ReadOptions::new(&ColumnMapping::default(), &SkuParser::default()),
+            &mut vec![],
+        )
+        .expect("read orders failed");
+        assert_eq!(orders.len(), 3);
+        assert_eq!(orders[0].id, "1232693822339834520");
+        assert_eq!(orders[0].leave_msg, "尽快发货 谢谢");
+        // rowspan 合并的订单信息，后续商品行属于同一订单
+        assert_eq!(orders[1].id, orders[0].id);
+        assert_eq!(orders[1].group, orders[0].group);
+        assert_eq!(orders[1].total_count, 1);
+        assert_eq!(orders[2].id, "1203824045541581939");
+        assert_eq!(orders[2].total_count, 2);
+    }
+
     #[test]
-    fn test_build_index() {
-        let mut range = Range::<DataType>::new((0, 0), (5, 2));
-        range.set_value((0, 1), DataType::String(String::from("hello")));
-        range.set_value((0, 2), DataType::String(String::from("world")));
+    fn test_read_orders_all_sheets() {
+        let mapping = ColumnMapping::default();
+        let sku = SkuParser::default();
+        let options = ReadOptions {
+            all_sheets: true,
+            ..ReadOptions::new(&mapping, &sku)
+        };
+        let orders = read("./testdatas/multi_sheet.xlsx", &options, &mut vec![])
+            .expect("read orders failed");
+        // 汇总 sheet 没有订单表头，被跳过
+        assert_eq!(orders.len(), 5);
+        assert_eq!(orders[0].source.sheet, "店铺A");
+        assert_eq!(orders[0].source.row, 2);
+        assert_eq!(orders[1].group, orders[0].group);
+        assert_eq!(orders[3].source.sheet, "店铺B");
+        assert_eq!(orders[3].source.file, "./testdatas/multi_sheet.xlsx");
+        // 不同 sheet 中的订单不在同一组
+        assert_ne!(orders[3].group, orders[2].group);
 
-        let title_index = build_index(&range).unwrap();
-        assert_eq!(title_index.len(), 2);
-        assert_eq!(title_index.get("hello").unwrap(), &1);
-        assert_eq!(title_index.get("world").unwrap(), &2);
+        // 跨 sheet 重复的订单被去掉
+        let paths = vec![PathBuf::from("./testdatas/multi_sheet.xlsx")];
+        let res = read_all(
+            &paths,
+            &options,
+            &ItemMatcher::new("ax199").unwrap(),
+            &mut vec![],
+        )
+        .unwrap();
+        assert_eq!(res.read, 5);
+        assert_eq!(res.unique, 4);
+        // 店铺B 中的 1002 与店铺A 中的内容相同
+        assert_eq!(res.duplicates.len(), 1);
+        assert_eq!(res.duplicates[0].order.id, "1002");
+        assert_eq!(res.duplicates[0].order.source.sheet, "店铺B");
+        assert_eq!(res.duplicates[0].kept_source.sheet, "店铺A");
+        assert_eq!(res.duplicates[0].kept_source.row, 4);
+        assert!(!res.duplicates[0].conflict());
+        let orders = res.orders;
+        assert_eq!(orders.len(), 4);
+
+        let orders = read(
+            "./testdatas/multi_sheet.xlsx",
+            &ReadOptions::new(&ColumnMapping::default(), &SkuParser::default()),
+            &mut vec![],
+        )
+        .expect("read orders failed");
+        assert_eq!(orders.len(), 3);
     }
 
     #[test]
-    fn test_read_orders() {
-        read_orders("./testdatas/src.xls").expect("read orders failed");
+    fn test_read_orders_offset() {
+        let mut orders = Vec::new();
+        let mut offset = 0;
+        for path in ["./testdatas/src_gbk.csv", "./testdatas/src_html.xls"] {
+            read_orders(
+                path,
+                &ReadOptions::new(&ColumnMapping::default(), &SkuParser::default()),
+                &mut vec![],
+                &mut offset,
+                &mut |order| orders.push(order),
+            )
+            .expect("read orders failed");
+        }
+        assert_eq!(orders.len(), 6);
+        assert_eq!(orders[3].source.file, "./testdatas/src_html.xls");
+        assert!(orders[3].group > orders[2].group);
+        assert_eq!(orders[4].group, orders[3].group);
+        assert_eq!(offset, orders[5].group);
+    }
+
+    // export_lines 合成导出文件的数据行，约三分之一的订单有两个商品，
+    // 每 50 个订单有一个重复导出，每 7 个订单来自同一收货人
+    fn export_lines(rows: usize) -> impl Iterator<Item = Vec<String>> {
+        let buyers = rows / 7 + 1;
+        (1..)
+            .flat_map(move |id: usize| {
+                let order_id = if id.is_multiple_of(50) { id - 1 } else { id };
+                let buyer = id % buyers;
+                let item = if id.is_multiple_of(4) {
+                    "AX200"
+                } else {
+                    "AX199"
+                };
+                let line: Vec<String> = vec![
+                    format!("{}", 1_000_000_000_000 + order_id),
+                    String::from("42"),
+                    String::from("等待卖家发货"),
+                    format!("买家{}", buyer),
+                    format!("广东省 深圳市 南山区 科技园{}号", buyer),
+                    String::new(),
+                    format!("{}", 13_800_000_000 + buyer),
+                    format!("{} 20色秋衣套装 颜色: 胡萝卜 适合身高: 110cm", item),
+                    String::from("14"),
+                    String::from("2"),
+                    String::new(),
+                ];
+                let mut lines = vec![line.clone()];
+                if id.is_multiple_of(3) {
+                    let mut next = vec![String::new(); 7];
+                    next.push(String::from(
+                        "AX199 20色秋衣套装 颜色: 长颈鹿 适合身高: 120cm",
+                    ));
+                    next.extend(line.into_iter().skip(8));
+                    lines.push(next);
+                }
+                lines
+            })
+            .take(rows)
+    }
+
+    // large_export 生成合成导出文件，文件保留在临时目录中复用
+    fn large_export(rows: usize, xlsx: bool) -> std::path::PathBuf {
+        let title = vec![
+            "订单编号",
+            "实付款(元)",
+            "订单状态",
+            "收货人姓名",
+            "收货地址",
+            "联系电话",
+            "联系手机",
+            "货品标题",
+            "单价(元)",
+            "数量",
+            "买家留言",
+        ];
+        let ext = if xlsx { "xlsx" } else { "csv" };
+        let path = std::env::temp_dir().join(format!("qlion_bench_{}.{}", rows, ext));
+        if path.exists() {
+            return path;
+        }
+
+        if xlsx {
+            let mut wb = rust_xlsxwriter::Workbook::new();
+            let sheet = wb.add_worksheet();
+            sheet.set_name("orders").unwrap();
+            let title = title.iter().map(|v| v.to_string()).collect();
+            for (row, line) in std::iter::once(title).chain(export_lines(rows)).enumerate() {
+                for (col, v) in line.iter().enumerate() {
+                    sheet.write_string(row as u32, col as u16, v).unwrap();
+                }
+            }
+            wb.save(&path).unwrap();
+        } else {
+            use std::io::Write;
+            let mut w = std::io::BufWriter::new(std::fs::File::create(&path).unwrap());
+            writeln!(w, "{}", title.join(",")).unwrap();
+            for line in export_lines(rows) {
+                writeln!(w, "{}", line.join(",")).unwrap();
+            }
+        }
+        path
+    }
+
+    // peak_rss 进程的内存峰值，单位 MB，只在 linux 下可用
+    fn peak_rss() -> f64 {
+        std::fs::read_to_string("/proc/self/status")
+            .ok()
+            .and_then(|status| {
+                status
+                    .lines()
+                    .find(|line| line.starts_with("VmHWM:"))
+                    .and_then(|line| line.split_whitespace().nth(1))
+                    .and_then(|kb| kb.parse::<f64>().ok())
+            })
+            .map(|kb| kb / 1024.0)
+            .unwrap_or(0.0)
+    }
+
+    // bench 读取合成文件并执行完整流程，打印各阶段耗时与内存峰值，
+    // 第一次运行会生成文件，需要单独运行并且文件已存在时才能得到准确的内存峰值：
+    // QLION_BENCH_ROWS=500000 cargo test --release bench_csv -- --ignored --nocapture
+    fn bench(xlsx: bool) {
+        let rows = std::env::var("QLION_BENCH_ROWS")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(200_000);
+        let path = large_export(rows, xlsx);
+        let base = peak_rss();
+        let mapping = ColumnMapping::default();
+        let sku = SkuParser::default();
+        let options = ReadOptions::new(&mapping, &sku);
+
+        let now = std::time::Instant::now();
+        let res = read_all(
+            std::slice::from_ref(&path),
+            &options,
+            &ItemMatcher::new("ax199").unwrap(),
+            &mut vec![],
+        )
+        .expect("read orders failed");
+        let read = now.elapsed();
+        let count = res.read;
+        let orders = res.orders;
+        let mut merges = vec![];
+        let orders = opr::merge_same_order(orders, &mut merges);
+        let mut orders = opr::merge_diff_order(
+            orders,
+            &opr::MergeKey::default(),
+            &Overrides::default(),
+            &mut merges,
+        );
+        opr::mark_same_phone_order(&mut orders);
+        let total = now.elapsed();
+        println!(
+            "{}: {} rows, read {:.2}s ({:.0} rows/s), pipeline {:.2}s, {} orders, peak rss {:.0} MB (+{:.0} MB)",
+            path.display(),
+            count,
+            read.as_secs_f64(),
+            count as f64 / read.as_secs_f64(),
+            (total - read).as_secs_f64(),
+            orders.len(),
+            peak_rss(),
+            peak_rss() - base,
+        );
+    }
+
+    #[test]
+    #[ignore]
+    fn bench_csv() {
+        bench(false);
+    }
+
+    #[test]
+    #[ignore]
+    fn bench_xlsx() {
+        bench(true);
+    }
+
+    #[test]
+    fn test_read_all_items() {
+        let mapping = ColumnMapping::default();
+        let sku = SkuParser::default();
+        let options = ReadOptions::new(&mapping, &sku);
+        let paths = vec![PathBuf::from("./testdatas/src.xls")];
+        let items = ItemMatcher::new(r"ax199,/AX2\d+/").unwrap();
+        let orders = read_all(&paths, &options, &items, &mut vec![])
+            .unwrap()
+            .orders;
+        assert!(orders.iter().any(|order| order.matched_item == "ax199"));
+        assert!(orders
+            .iter()
+            .filter(|order| order.sku.item_no == "AX200")
+            .all(|order| order.matched_item == r"/AX2\d+/"));
+
+        // 只匹配 AX199 时，同一订单中的其它货号记录为拆出商品
+        let items = ItemMatcher::new("ax199").unwrap();
+        let res = read_all(&paths, &options, &items, &mut vec![]).unwrap();
+        assert!(!res.split_lines.is_empty());
+        for line in res.split_lines.iter() {
+            assert_ne!(line.order.sku.item_no, "AX199");
+            assert!(res
+                .orders
+                .iter()
+                .any(|order| order.id == line.order.id && order.splited));
+        }
+
+        // 手动指定排除的订单不输出
+        let overrides = Overrides::parse(r#"exclude = ["1232693822339834520"]"#).unwrap();
+        let options = ReadOptions {
+            overrides: Some(&overrides),
+            ..ReadOptions::new(&mapping, &sku)
+        };
+        let res = read_all(&paths, &options, &items, &mut vec![]).unwrap();
+        assert_eq!(res.excluded, 3);
+        assert!(res
+            .orders
+            .iter()
+            .all(|order| order.id != "1232693822339834520"));
+
+        let path = generate_dst_path("ax199,/AX2\\d+/", &orders);
+        assert!(path.contains("AX199_AX200 "));
+        let path = generate_dst_path("AX1*", &[]);
+        assert!(path.contains("AX1_ "));
+        let many: Vec<Order> = (0..100)
+            .map(|n| {
+                let mut order = Order::empty();
+                order.sku.item_no = format!("AX{}", 100 + n);
+                order
+            })
+            .collect();
+        let path = generate_dst_path("/AX\\d+/", &many);
+        assert!(path.contains("AX100_AX101_AX102等100款 "));
+    }
+
+    #[test]
+    fn test_save_merge_records() {
+        let mapping = ColumnMapping::default();
+        let sku = SkuParser::default();
+        let options = ReadOptions::new(&mapping, &sku);
+        let paths = vec![PathBuf::from("./testdatas/src.xls")];
+        let items = ItemMatcher::new("ax199").unwrap();
+        let orders = read_all(&paths, &options, &items, &mut vec![])
+            .unwrap()
+            .orders;
+        let MergeResult {
+            orders,
+            records: merges,
+            ..
+        } = merge_orders(
+            orders,
+            &MergeOptions {
+                key: &opr::MergeKey::default(),
+                overrides: &Overrides::default(),
+                fuzzy: &fuzzy::FuzzySettings::default(),
+            },
+        );
+        let merged: usize = orders.iter().map(|order| order.merged.len()).sum();
+        assert_eq!(
+            merges.iter().filter(|r| r.kind == "同一买家").count(),
+            merged
+        );
+
+        let path = env::temp_dir().join("qlion_test_merges.jsonl");
+        save_merge_records(&path, &merges).unwrap();
+        let content = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let lines: Vec<serde_json::Value> = content
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(lines.len(), merges.len());
+        assert_eq!(lines[0]["into"]["id"], merges[0].into.id.as_str());
+        assert_eq!(
+            lines[0]["merged"]["source"]["row"],
+            merges[0].merged.source.row
+        );
+    }
+
+    #[test]
+    fn test_config_new() {
+        let args = vec!["qlion", "src.xls", "ax199", "--config", "shop.toml"];
+        let config = Config::new(args.into_iter().map(String::from)).unwrap();
+        assert_eq!(config.src_paths, vec!["src.xls"]);
+        assert_eq!(config.item_no, "ax199");
+        assert_eq!(config.config_path.as_deref(), Some("shop.toml"));
+        assert!(!config.strict);
+
+        let args = vec!["qlion", "--strict", "src.xls", "ax199"];
+        let config = Config::new(args.into_iter().map(String::from)).unwrap();
+        assert_eq!(config.src_paths, vec!["src.xls"]);
+        assert!(config.strict);
+
+        let args = vec!["qlion", "--all-sheets", "a.xls", "shops/", "ax199"];
+        let config = Config::new(args.into_iter().map(String::from)).unwrap();
+        assert_eq!(config.src_paths, vec!["a.xls", "shops/"]);
+        assert_eq!(config.item_no, "ax199");
+        assert!(config.all_sheets);
+        assert!(!config.per_item);
+
+        let args = vec!["qlion", "src.xls", "ax1*", "--per-item"];
+        let config = Config::new(args.into_iter().map(String::from)).unwrap();
+        assert_eq!(config.item_no, "ax1*");
+        assert!(config.per_item);
+
+        let args = vec![
+            "qlion",
+            "--ignore-status",
+            "--merge-with",
+            "shop,buyer_account",
+        ];
+        let args = args.into_iter().chain(["src.xls", "ax199"]);
+        let config = Config::new(args.map(String::from)).unwrap();
+        assert!(config.ignore_status);
+        assert_eq!(config.merge_with, vec!["shop", "buyer_account"]);
+        assert_eq!(config.src_paths, vec!["src.xls"]);
+
+        let args = vec!["qlion", "src.xls", "ax199", "--overrides", "manual.toml"];
+        let config = Config::new(args.into_iter().map(String::from)).unwrap();
+        assert_eq!(config.overrides_path.as_deref(), Some("manual.toml"));
+
+        let args = vec!["qlion", "src.xls"];
+        assert!(Config::new(args.into_iter().map(String::from)).is_err());
+
+        let args = vec!["qlion", "src.xls", "--config"];
+        assert!(Config::new(args.into_iter().map(String::from)).is_err());
+
+        let args = vec!["qlion", "src.xls", "ax199", "--per_item"];
+        match Config::new(args.into_iter().map(String::from)) {
+            Err(err) => assert!(err.starts_with("Unknown option --per_item, usage: qlion")),
+            Ok(_) => panic!("unknown option is accepted"),
+        }
     }
 }
@@ -0,0 +1,223 @@
+use crate::platform::Platform;
+use std::collections::HashMap;
+
+// Field 订单需要读取的字段
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Field {
+    Id,
+    PayAmount,
+    Status,
+    Consignee,
+    ShippingAddress,
+    Phone,
+    Telephone,
+    ItemName,
+    Count,
+    Price,
+    LeaveMsg,
+    Shop,
+    BuyerAccount,
+}
+
+impl Field {
+    pub const ALL: [Field; 13] = [
+        Field::Id,
+        Field::PayAmount,
+        Field::Status,
+        Field::Consignee,
+        Field::ShippingAddress,
+        Field::Phone,
+        Field::Telephone,
+        Field::ItemName,
+        Field::Count,
+        Field::Price,
+        Field::LeaveMsg,
+        Field::Shop,
+        Field::BuyerAccount,
+    ];
+
+    // for_merge 只用于合并条件的字段，不用于识别表头，避免把汇总表中的“店铺”当作订单表头
+    pub fn for_merge(self) -> bool {
+        matches!(self, Field::Shop | Field::BuyerAccount)
+    }
+
+    // title 返回标准列名，Order::from_row 按标准列名取值
+    pub fn title(self) -> &'static str {
+        match self {
+            Field::Id => "订单编号",
+            Field::PayAmount => "实付款(元)",
+            Field::Status => "订单状态",
+            Field::Consignee => "收货人姓名",
+            Field::ShippingAddress => "收货地址",
+            Field::Phone => "联系手机",
+            Field::Telephone => "联系电话",
+            Field::ItemName => "货品标题",
+            Field::Count => "数量",
+            Field::Price => "单价(元)",
+            Field::LeaveMsg => "买家留言",
+            Field::Shop => "店铺名称",
+            Field::BuyerAccount => "买家会员名",
+        }
+    }
+
+    // key 返回配置文件中使用的字段名
+    pub fn key(self) -> &'static str {
+        match self {
+            Field::Id => "id",
+            Field::PayAmount => "pay_amount",
+            Field::Status => "status",
+            Field::Consignee => "consignee",
+            Field::ShippingAddress => "shipping_address",
+            Field::Phone => "phone",
+            Field::Telephone => "telephone",
+            Field::ItemName => "item_name",
+            Field::Count => "count",
+            Field::Price => "price",
+            Field::LeaveMsg => "leave_msg",
+            Field::Shop => "shop",
+            Field::BuyerAccount => "buyer_account",
+        }
+    }
+
+    // from_key 配置文件中的字段既可以写 key，也可以写标准列名
+    pub fn from_key(key: &str) -> Option<Field> {
+        Field::ALL
+            .iter()
+            .copied()
+            .find(|field| field.key() == key || field.title() == key)
+    }
+
+    // default_aliases 内置的列名别名，标准列名排在第一位
+    fn default_aliases(self) -> &'static [&'static str] {
+        match self {
+            Field::Id => &["订单编号", "订单号", "主订单编号", "订单ID"],
+            Field::PayAmount => &["实付款(元)", "实付金额", "买家实付金额", "实收款(元)"],
+            Field::Status => &["订单状态"],
+            Field::Consignee => &["收货人姓名", "收货人", "收件人"],
+            Field::ShippingAddress => &["收货地址", "详细地址", "收件地址"],
+            Field::Phone => &["联系手机", "收货人手机号", "手机号", "手机"],
+            Field::Telephone => &["联系电话", "固定电话"],
+            Field::ItemName => &["货品标题", "商品标题", "宝贝标题", "商品名称"],
+            Field::Count => &["数量", "购买数量", "商品数量", "宝贝数量"],
+            Field::Price => &["单价(元)", "单价", "商品单价"],
+            Field::LeaveMsg => &["买家留言", "买家备注", "留言"],
+            Field::Shop => &["店铺名称", "店铺", "店铺名"],
+            Field::BuyerAccount => &["买家会员名", "买家账号", "买家昵称", "买家ID"],
+        }
+    }
+}
+
+// normalize_title 忽略列名前后的空白，以及全角半角括号的差异
+fn normalize_title(title: &str) -> String {
+    title.trim().replace('（', "(").replace('）', ")")
+}
+
+// ColumnMapping 字段到列名别名的映射
+#[derive(Debug, Default)]
+pub struct ColumnMapping {
+    configured: HashMap<Field, Vec<String>>, // 配置文件中的别名
+}
+
+impl ColumnMapping {
+    pub fn new(columns: &HashMap<String, Vec<String>>) -> Result<ColumnMapping, String> {
+        let mut mapping = ColumnMapping::default();
+        for (key, aliases) in columns.iter() {
+            let field =
+                Field::from_key(key).ok_or_else(|| format!("unknown column field: {}", key))?;
+            mapping
+                .configured
+                .entry(field)
+                .or_default()
+                .extend(aliases.iter().cloned());
+        }
+        Ok(mapping)
+    }
+
+    // resolve 将表头索引转换为以标准列名为 key 的索引
+    // 别名的匹配顺序：配置的别名、平台的别名、内置别名
+    pub fn resolve(
+        &self,
+        title_index: &HashMap<String, usize>,
+        platform: &dyn Platform,
+    ) -> HashMap<String, usize> {
+        let normalized: HashMap<String, usize> = title_index
+            .iter()
+            .map(|(title, i)| (normalize_title(title), *i))
+            .collect();
+
+        let mut res = HashMap::new();
+        for field in Field::ALL.iter() {
+            let configured = self.configured.get(field).into_iter().flatten();
+            let builtin = platform
+                .aliases(*field)
+                .iter()
+                .chain(field.default_aliases().iter())
+                .copied();
+            let found = configured
+                .map(|alias| alias.as_str())
+                .chain(builtin)
+                .find_map(|alias| normalized.get(&normalize_title(alias)));
+            if let Some(i) = found {
+                res.insert(String::from(field.title()), *i);
+            }
+        }
+        res
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::platform::{Generic, Taobao};
+
+    fn title_index(titles: &[&str]) -> HashMap<String, usize> {
+        titles
+            .iter()
+            .enumerate()
+            .map(|(i, title)| (title.to_string(), i))
+            .collect()
+    }
+
+    #[test]
+    fn test_resolve_default_aliases() {
+        let mapping = ColumnMapping::default();
+        let index = mapping.resolve(
+            &title_index(&["订单号", " 收货人 ", "实付款（元）", "其它"]),
+            &Generic,
+        );
+        assert_eq!(index.len(), 3);
+        assert_eq!(index["订单编号"], 0);
+        assert_eq!(index["收货人姓名"], 1);
+        assert_eq!(index["实付款(元)"], 2);
+    }
+
+    #[test]
+    fn test_resolve_configured_aliases() {
+        let mut columns = HashMap::new();
+        columns.insert(String::from("id"), vec![String::from("平台单号")]);
+        columns.insert(String::from("货品标题"), vec![String::from("SKU名称")]);
+        let mapping = ColumnMapping::new(&columns).unwrap();
+
+        // 配置的别名优先于内置别名
+        let index = mapping.resolve(&title_index(&["订单编号", "平台单号", "SKU名称"]), &Generic);
+        assert_eq!(index["订单编号"], 1);
+        assert_eq!(index["货品标题"], 2);
+
+        columns.insert(String::from("unknow"), vec![String::from("x")]);
+        assert!(ColumnMapping::new(&columns).is_err());
+    }
+
+    #[test]
+    fn test_resolve_platform_aliases() {
+        let mapping = ColumnMapping::default();
+        let index = mapping.resolve(
+            &title_index(&["订单编号", "宝贝标题", "宝贝总数量", "买家实际支付金额"]),
+            &Taobao,
+        );
+        assert_eq!(index["订单编号"], 0);
+        assert_eq!(index["货品标题"], 1);
+        assert_eq!(index["数量"], 2);
+        assert_eq!(index["实付款(元)"], 3);
+    }
+}
@@ -0,0 +1,212 @@
+use serde::{Serialize, Serializer};
+use std::{
+    fmt,
+    iter::Sum,
+    ops::{Add, AddAssign, Mul, Sub, SubAssign},
+};
+
+// Money 金额，以分为单位的整数，避免浮点数求和出现 179.70000000000002 这样的误差
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Money(i64);
+
+// CURRENCY 金额文本中忽略的货币符号、单位和千分位
+const CURRENCY: [char; 6] = ['¥', '￥', '元', ',', '，', ' '];
+
+impl Money {
+    pub const ZERO: Money = Money(0);
+
+    pub fn from_cents(cents: i64) -> Money {
+        Money(cents)
+    }
+
+    // from_f64 单元格中的数字金额，四舍五入到分
+    pub fn from_f64(v: f64) -> Money {
+        Money((v * 100.0).round() as i64)
+    }
+
+    // parse 解析文本金额，如 "12.5"、"¥12.50"、"1,299.00元"、"-3.456"，
+    // 按文本逐位解析，超过两位的小数四舍五入到分
+    pub fn parse(raw: &str) -> Option<Money> {
+        let text: String = raw
+            .trim()
+            .chars()
+            .filter(|c| !CURRENCY.contains(c))
+            .collect();
+        let (negative, text) = match text.strip_prefix('-') {
+            Some(v) => (true, v),
+            None => (false, text.strip_prefix('+').unwrap_or(&text)),
+        };
+        let (integer, fraction) = text.split_once('.').unwrap_or((text, ""));
+        if integer.is_empty() && fraction.is_empty() {
+            return None;
+        }
+        if !integer
+            .chars()
+            .chain(fraction.chars())
+            .all(|c| c.is_ascii_digit())
+        {
+            return None;
+        }
+
+        let mut cents = if integer.is_empty() {
+            0
+        } else {
+            integer.parse::<i64>().ok()?.checked_mul(100)?
+        };
+        let digits: Vec<i64> = fraction.bytes().map(|c| (c - b'0') as i64).collect();
+        let digit = |n: usize| digits.get(n).copied().unwrap_or(0);
+        cents += digit(0) * 10 + digit(1);
+        if digit(2) >= 5 {
+            cents += 1;
+        }
+        Some(Money(if negative { -cents } else { cents }))
+    }
+
+    // to_f64 写入 xlsx 的数字，分转换为元后是最接近的浮点数，显示时没有误差
+    pub fn to_f64(self) -> f64 {
+        self.0 as f64 / 100.0
+    }
+
+    // allocate 按 weights 的比例分摊金额，weights 都为 0 时平均分摊，最后一份取余数，保证合计不变
+    pub fn allocate(self, weights: &[Money]) -> Vec<Money> {
+        let total: i128 = weights.iter().map(|w| w.0 as i128).sum();
+        let mut rest = self;
+        let mut res: Vec<Money> = Vec::with_capacity(weights.len());
+        for (n, weight) in weights.iter().enumerate() {
+            let share = if n + 1 == weights.len() {
+                rest
+            } else if total != 0 {
+                Money(div_round(self.0 as i128 * weight.0 as i128, total) as i64)
+            } else {
+                Money(div_round(self.0 as i128, weights.len() as i128) as i64)
+            };
+            rest -= share;
+            res.push(share);
+        }
+        res
+    }
+}
+
+// div_round 整数除法，四舍五入
+fn div_round(a: i128, b: i128) -> i128 {
+    let (a, b) = if b < 0 { (-a, -b) } else { (a, b) };
+    if a >= 0 {
+        (a + b / 2) / b
+    } else {
+        -((-a + b / 2) / b)
+    }
+}
+
+impl fmt::Display for Money {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let sign = if self.0 < 0 { "-" } else { "" };
+        let cents = self.0.unsigned_abs();
+        write!(f, "{}{}.{:02}", sign, cents / 100, cents % 100)
+    }
+}
+
+// 合并说明的 jsonl 中写成以元为单位的数字
+impl Serialize for Money {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_f64(self.to_f64())
+    }
+}
+
+impl Add for Money {
+    type Output = Money;
+
+    fn add(self, other: Money) -> Money {
+        Money(self.0 + other.0)
+    }
+}
+
+impl AddAssign for Money {
+    fn add_assign(&mut self, other: Money) {
+        self.0 += other.0;
+    }
+}
+
+impl Sub for Money {
+    type Output = Money;
+
+    fn sub(self, other: Money) -> Money {
+        Money(self.0 - other.0)
+    }
+}
+
+impl SubAssign for Money {
+    fn sub_assign(&mut self, other: Money) {
+        self.0 -= other.0;
+    }
+}
+
+// 单价乘以数量
+impl Mul<i64> for Money {
+    type Output = Money;
+
+    fn mul(self, count: i64) -> Money {
+        Money(self.0 * count)
+    }
+}
+
+impl Sum for Money {
+    fn sum<I: Iterator<Item = Money>>(iter: I) -> Money {
+        iter.fold(Money::ZERO, Add::add)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(Money::parse("12.5"), Some(Money::from_cents(1250)));
+        assert_eq!(Money::parse("¥12.50"), Some(Money::from_cents(1250)));
+        assert_eq!(
+            Money::parse(" ￥ 1,299.00元"),
+            Some(Money::from_cents(129900))
+        );
+        assert_eq!(Money::parse("12"), Some(Money::from_cents(1200)));
+        assert_eq!(Money::parse(".5"), Some(Money::from_cents(50)));
+        assert_eq!(Money::parse("-3.456"), Some(Money::from_cents(-346)));
+        assert_eq!(Money::parse("0.004"), Some(Money::ZERO));
+        assert_eq!(Money::parse(""), None);
+        assert_eq!(Money::parse("¥"), None);
+        assert_eq!(Money::parse("12.5.0"), None);
+        assert_eq!(Money::parse("1e3"), None);
+    }
+
+    #[test]
+    fn test_arithmetic() {
+        let price = Money::parse("59.9").unwrap();
+        let total = price * 3;
+        assert_eq!(total.to_string(), "179.70");
+        assert_eq!(total.to_f64(), 179.7);
+        assert_eq!(Money::from_f64(0.1 + 0.2), Money::from_cents(30));
+        let sum: Money = vec![price, price, price].into_iter().sum();
+        assert_eq!(sum, total);
+        assert_eq!(Money::from_cents(-5).to_string(), "-0.05");
+        assert_eq!(serde_json::to_string(&total).unwrap(), "179.7");
+    }
+
+    #[test]
+    fn test_allocate() {
+        let weights = [6000, 3000, 1000].map(Money::from_cents);
+        let shares = Money::from_cents(9000).allocate(&weights);
+        assert_eq!(shares, [5400, 2700, 900].map(Money::from_cents));
+
+        // 除不尽时余数给最后一份
+        let weights = [1, 1, 1].map(Money::from_cents);
+        let shares = Money::from_cents(100).allocate(&weights);
+        assert_eq!(shares, [33, 33, 34].map(Money::from_cents));
+
+        // 总价都为 0 时平均分摊
+        let shares = Money::from_cents(100).allocate(&[Money::ZERO, Money::ZERO]);
+        assert_eq!(shares, [50, 50].map(Money::from_cents));
+    }
+}
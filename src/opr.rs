@@ -1,54 +1,507 @@
-use crate::order::Order;
-use std::{collections::HashMap, hash::Hash, hash::Hasher};
+use crate::address;
+use crate::item::ItemMatcher;
+use crate::mapping::Field;
+use crate::money::Money;
+use crate::order::{Order, Source};
+use crate::overrides::Overrides;
+use crate::phone::Phone;
+use crate::xlsx::{self, Row};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{hash_map::DefaultHasher, BTreeMap, HashMap, HashSet},
+    hash::Hash,
+    hash::Hasher,
+};
 
-// remove_repeat 删除重复的订单
-pub fn remove_repeat(orders: Vec<Order>) -> Vec<Order> {
-    let mut order_group = HashMap::<String, u32>::new();
+// fingerprint 比较重复导出的内容是否相同，只保存 hash 以减少内存
+fn fingerprint<T: Hash + ?Sized>(value: &T) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    value.hash(&mut hasher);
+    hasher.finish()
+}
 
-    orders
-        .into_iter()
-        .filter(|order| match order_group.get(&order.id) {
-            Some(group) => order.group == *group,
+// GroupPrint 一组中用于比较的内容，商品按行累加 hash，与商品的顺序无关
+#[derive(Clone, Copy, PartialEq)]
+struct GroupPrint {
+    receiver: u64, // 收货人、地址、电话
+    other: u64,    // 订单状态、买家留言
+    items: u64,    // 每个商品的标题
+    lines: u64,    // 每个商品的标题与数量
+}
+
+impl GroupPrint {
+    fn new(order: &Order) -> GroupPrint {
+        GroupPrint {
+            receiver: fingerprint(&(
+                &order.consignee,
+                &order.shipping_address,
+                &order.phone,
+                &order.telephone,
+            )),
+            other: fingerprint(&(&order.status, &order.leave_msg)),
+            items: 0,
+            lines: 0,
+        }
+    }
+
+    fn add_line(&mut self, order: &Order) {
+        self.items = self.items.wrapping_add(fingerprint(&order.item_name));
+        self.lines = self
+            .lines
+            .wrapping_add(fingerprint(&(&order.item_name, order.total_count)));
+    }
+
+    // diff 与保留的组不同的地方
+    fn diff(&self, kept: &GroupPrint) -> Vec<&'static str> {
+        let mut diff = Vec::new();
+        if kept.receiver != self.receiver {
+            diff.push("收货信息");
+        }
+        if kept.items != self.items {
+            diff.push("商品");
+        } else if kept.lines != self.lines {
+            diff.push("数量");
+        }
+        if kept.other != self.other {
+            diff.push("其它");
+        }
+        diff
+    }
+}
+
+// KeptOrder 第一次出现的订单，只记住所在的组、位置和用于比较的 hash
+struct KeptOrder {
+    group: u32,
+    sheet: u32, // 文件与 sheet 在 RemoveRepeat::sheets 中的位置
+    row: u32,
+    print: GroupPrint,
+}
+
+// PendingGroup 正在读取的重复的组，读完整组后再与保留的组比较
+struct PendingGroup {
+    group: u32,
+    print: GroupPrint,
+    orders: Vec<Order>,
+}
+
+// Duplicate 重复导出被删除的行
+#[derive(Debug)]
+pub struct Duplicate {
+    pub order: Order,
+    pub kept_group: u32,         // 保留的组
+    pub kept_source: Source,     // 保留的组第一次出现的位置
+    pub diff: Vec<&'static str>, // 与保留的内容不同的地方
+}
+
+impl Duplicate {
+    // conflict 收货信息、商品或数量不同时需要人工核对
+    pub fn conflict(&self) -> bool {
+        self.diff.iter().any(|v| *v != "其它")
+    }
+
+    pub fn excel_title_row() -> Row {
+        xlsx::row![
+            "订单编号",
+            "来源",
+            "重复的组",
+            "首次出现",
+            "比较",
+            "需人工核对",
+            "收货人姓名",
+            "收货地址",
+            "货品标题",
+            "数量"
+        ]
+    }
+
+    pub fn as_excel_row(&self) -> Row {
+        let source = |s: &Source| format!("{} {} {}", s.file, s.sheet, s.row);
+        let diff = if self.diff.is_empty() {
+            String::from("相同")
+        } else {
+            format!("不同: {}", self.diff.join("、"))
+        };
+        xlsx::row![
+            self.order.id.clone(),
+            source(&self.order.source),
+            self.kept_group as f64,
+            source(&self.kept_source),
+            diff,
+            if self.conflict() { "是" } else { "" },
+            self.order.consignee.clone(),
+            self.order.shipping_address.clone(),
+            self.order.item_name.clone(),
+            self.order.total_count as f64
+        ]
+    }
+}
+
+// RemoveRepeat 逐个判断订单是否为重复导出：同一订单编号出现在不同的组中时为重复，
+// 重复的组读完后与第一次出现的组整体比较，被删除的行记录在 duplicates 中，
+// 读完所有订单后需要调用 finish
+#[derive(Default)]
+pub struct RemoveRepeat {
+    kept: HashMap<String, KeptOrder>,
+    sheets: Vec<(String, String)>, // 出现过的文件与 sheet
+    pending: Option<PendingGroup>,
+    pub duplicates: Vec<Duplicate>,
+}
+
+impl RemoveRepeat {
+    // sheet_index 文件与 sheet 的位置，同一个 sheet 的订单是连续的，通常只需要比较最后一个
+    fn sheet_index(&mut self, source: &Source) -> u32 {
+        let found = self
+            .sheets
+            .iter()
+            .rposition(|(file, sheet)| *file == source.file && *sheet == source.sheet);
+        let index = found.unwrap_or_else(|| {
+            self.sheets
+                .push((source.file.clone(), source.sheet.clone()));
+            self.sheets.len() - 1
+        });
+        index as u32
+    }
+
+    // keep 保留时返回订单
+    pub fn keep(&mut self, order: Order) -> Option<Order> {
+        if let Some(pending) = self.pending.as_mut() {
+            if pending.group == order.group && pending.orders[0].id == order.id {
+                pending.print.add_line(&order);
+                pending.orders.push(order);
+                return None;
+            }
+            self.finish();
+        }
+
+        let sheet = self.sheet_index(&order.source);
+        let kept = match self.kept.get_mut(&order.id) {
+            Some(kept) => kept,
             None => {
-                order_group.insert(order.id.clone(), order.group);
-                true
+                let mut print = GroupPrint::new(&order);
+                print.add_line(&order);
+                self.kept.insert(
+                    order.id.clone(),
+                    KeptOrder {
+                        group: order.group,
+                        sheet,
+                        row: order.source.row,
+                        print,
+                    },
+                );
+                return Some(order);
             }
-        })
-        .collect()
-}
-
-// remove_invalid_item 移除非条件商品
-pub fn remove_invalid_item(orders: Vec<Order>, item_no: &String) -> Vec<Order> {
-    let mut removed_orders = HashMap::<String, bool>::new();
-    let mut orders: Vec<Order> = orders
-        .into_iter()
-        .filter(|order| {
-            let res = order.item_no().to_lowercase() == item_no.to_lowercase();
-            if !res {
-                removed_orders.insert(order.id.clone(), true);
+        };
+        if kept.group == order.group {
+            kept.print.add_line(&order);
+            return Some(order);
+        }
+
+        let mut print = GroupPrint::new(&order);
+        print.add_line(&order);
+        self.pending = Some(PendingGroup {
+            group: order.group,
+            print,
+            orders: vec![order],
+        });
+        None
+    }
+
+    // finish 比较最后一个重复的组
+    pub fn finish(&mut self) {
+        let pending = match self.pending.take() {
+            Some(pending) => pending,
+            None => return,
+        };
+        let kept = &self.kept[&pending.orders[0].id];
+        let (file, sheet) = &self.sheets[kept.sheet as usize];
+        let kept_source = Source {
+            file: file.clone(),
+            sheet: sheet.clone(),
+            row: kept.row,
+        };
+        let diff = pending.print.diff(&kept.print);
+        for order in pending.orders.into_iter() {
+            self.duplicates.push(Duplicate {
+                kept_group: kept.group,
+                kept_source: kept_source.clone(),
+                diff: diff.clone(),
+                order,
+            });
+        }
+    }
+}
+
+// SplitLine 拆单时从订单中移除的商品，交给处理其它货号的人发货
+#[derive(Debug)]
+pub struct SplitLine {
+    pub order: Order, // 移除的商品，订单信息与保留的部分相同
+    pub kept: String, // 同一订单中保留的商品
+}
+
+impl SplitLine {
+    pub fn excel_title_row() -> Row {
+        xlsx::row![
+            "订单编号",
+            "并入订单",
+            "订单状态",
+            "收货人姓名",
+            "收货地址",
+            "联系手机",
+            "货品标题",
+            "数量",
+            "实付款(元)",
+            "总价(元)",
+            "买家留言",
+            "保留商品",
+            "来源"
+        ]
+    }
+
+    // as_excel_row merged_into 为保留部分合并后所在的订单
+    pub fn as_excel_row(&self, merged_into: &str) -> Row {
+        let order = &self.order;
+        xlsx::row![
+            order.id.clone(),
+            merged_into.to_string(),
+            order.status.clone(),
+            order.consignee.clone(),
+            order.shipping_address.clone(),
+            order.contact_phone().to_string(),
+            order.item_name.clone(),
+            order.total_count as f64,
+            order.pay_amount,
+            order.total_price,
+            order.leave_msg.clone(),
+            self.kept.clone(),
+            format!(
+                "{} {} {}",
+                order.source.file, order.source.sheet, order.source.row
+            )
+        ]
+    }
+}
+
+// ItemFilter 逐组移除非条件商品，去重后同一组的订单是连续的，只需要缓存当前组；
+// 拆单移除的商品记录在 split_lines 中，整个订单都不是条件商品的直接丢弃
+pub struct ItemFilter<'a> {
+    matcher: &'a ItemMatcher,
+    group: Vec<Order>,
+    pub split_lines: Vec<SplitLine>,
+}
+
+impl<'a> ItemFilter<'a> {
+    pub fn new(matcher: &'a ItemMatcher) -> ItemFilter<'a> {
+        ItemFilter {
+            matcher,
+            group: Vec::new(),
+            split_lines: Vec::new(),
+        }
+    }
+
+    // push 加入一个订单，进入新的组时返回上一组中保留的订单
+    pub fn push(&mut self, order: Order) -> Vec<Order> {
+        let res = match self.group.first() {
+            Some(first) if first.group != order.group => self.finish(),
+            _ => Vec::new(),
+        };
+        self.group.push(order);
+        res
+    }
+
+    // finish 返回当前组中保留的订单并记录匹配的条件，同一订单有商品被移除时标记为已拆
+    pub fn finish(&mut self) -> Vec<Order> {
+        let mut group = std::mem::take(&mut self.group);
+        allocate_pay_amount(&mut group);
+        let mut removed: Vec<Order> = Vec::new();
+        let mut orders: Vec<Order> = group
+            .into_iter()
+            .filter_map(|mut order| match self.matcher.find(&order.sku.item_no) {
+                Some(pattern) => {
+                    order.matched_item = pattern.to_string();
+                    Some(order)
+                }
+                None => {
+                    removed.push(order);
+                    None
+                }
+            })
+            .collect();
+
+        let removed_orders: HashSet<&str> = removed.iter().map(|order| order.id.as_str()).collect();
+        orders.iter_mut().for_each(|order| {
+            if removed_orders.contains(order.id.as_str()) {
+                order.splited = true;
             }
-            res
-        })
-        .collect();
+        });
+
+        for mut order in removed.into_iter() {
+            let kept: Vec<&str> = orders
+                .iter()
+                .filter(|kept| kept.id == order.id)
+                .map(|kept| kept.item_name.as_str())
+                .collect();
+            if !kept.is_empty() {
+                order.splited = true;
+                self.split_lines.push(SplitLine {
+                    kept: kept.join("\n"),
+                    order,
+                });
+            }
+        }
+
+        orders
+    }
+}
+
+// allocate_pay_amount 同一订单的商品行沿用上一行的实付款时，实付款是这几行共用的，
+// 按各行总价的比例分摊到每一行（总价都为 0 时平均分摊），最后一行取余数，保证合计不变；
+// 每行都带实付款时已经是每行的金额，即使金额相同也不分摊
+fn allocate_pay_amount(group: &mut [Order]) {
+    let mut start = 0;
+    for end in 1..=group.len() {
+        let shared = end < group.len() && {
+            let (last, order) = (&group[end - 1], &group[end]);
+            order.pay_shared && order.id == last.id
+        };
+        if shared {
+            continue;
+        }
+        let lines = &mut group[start..end];
+        if lines.len() > 1 {
+            let weights: Vec<Money> = lines.iter().map(|order| order.total_price).collect();
+            let shares = lines[0].pay_amount.allocate(&weights);
+            for (order, share) in lines.iter_mut().zip(shares) {
+                order.pay_amount = share;
+            }
+        }
+        start = end;
+    }
+}
 
-    orders.iter_mut().for_each(|order| {
-        if removed_orders.get(&order.id).is_some() {
+// split_by_item 按货号（不区分大小写）分开订单，按货号排序；
+// 一个订单的商品分到多个货号时，在每个货号中都标记为已拆
+pub fn split_by_item(orders: Vec<Order>) -> Vec<(String, Vec<Order>)> {
+    let mut order_items = HashMap::<String, HashSet<String>>::new();
+    orders.iter().for_each(|order| {
+        order_items
+            .entry(order.id.clone())
+            .or_default()
+            .insert(order.sku.item_no.to_uppercase());
+    });
+
+    let mut batches = BTreeMap::<String, Vec<Order>>::new();
+    orders.into_iter().for_each(|mut order| {
+        if order_items[&order.id].len() > 1 {
             order.splited = true;
         }
+        batches
+            .entry(order.sku.item_no.to_uppercase())
+            .or_default()
+            .push(order);
     });
+    batches.into_iter().collect()
+}
 
-    orders
+// MergeSide 合并时一方的订单，into 一方为合并前的内容
+#[derive(Debug, Serialize)]
+pub struct MergeSide {
+    pub id: String,         // 订单编号
+    pub item_name: String,  // 货品标题
+    pub count: i64,         // 数量
+    pub total_price: Money, // 数量*单价
+    pub pay_amount: Money,  // 实付款(元)
+    pub source: Source,     // 来源
+}
+
+impl MergeSide {
+    pub fn new(order: &Order) -> MergeSide {
+        MergeSide {
+            id: order.id.clone(),
+            item_name: order.item_name.clone(),
+            count: order.total_count,
+            total_price: order.total_price,
+            pay_amount: order.pay_amount,
+            source: order.source.clone(),
+        }
+    }
+}
+
+// MergeRecord 合并说明，客服向买家解释为什么合并
+#[derive(Debug, Serialize)]
+pub struct MergeRecord {
+    pub kind: &'static str,         // 同一订单 / 同一买家 / 疑似同一买家
+    pub matched: Vec<&'static str>, // 相同的字段
+    pub phone_basis: &'static str,  // 同一买家按哪个号码判断，同一订单为空
+    pub score: Option<f64>,         // 疑似同一买家自动合并时的相似度
+    pub into: MergeSide,            // 合并到的订单
+    pub merged: MergeSide,          // 被合并的订单
+}
+
+impl MergeRecord {
+    pub fn excel_title_row() -> Row {
+        xlsx::row![
+            "合并方式",
+            "相同字段",
+            "号码依据",
+            "相似度",
+            "合并到",
+            "合并前商品",
+            "合并前数量",
+            "合并前总价",
+            "合并前实付款",
+            "被合并订单",
+            "被合并商品",
+            "被合并数量",
+            "被合并总价",
+            "被合并实付款",
+            "被合并来源"
+        ]
+    }
+
+    pub fn as_excel_row(&self) -> Row {
+        let (into, merged) = (&self.into, &self.merged);
+        xlsx::row![
+            self.kind,
+            self.matched.join(","),
+            self.phone_basis,
+            self.score.map(|v| v.to_string()).unwrap_or_default(),
+            into.id.clone(),
+            into.item_name.clone(),
+            into.count as f64,
+            into.total_price,
+            into.pay_amount,
+            merged.id.clone(),
+            merged.item_name.clone(),
+            merged.count as f64,
+            merged.total_price,
+            merged.pay_amount,
+            format!(
+                "{} {} {}",
+                merged.source.file, merged.source.sheet, merged.source.row
+            )
+        ]
+    }
 }
 
-// merge_same_order 合并同id的订单
-pub fn merge_same_order(orders: Vec<Order>) -> Vec<Order> {
+// merge_same_order 合并同id的订单，每次合并记录到 records
+pub fn merge_same_order(orders: Vec<Order>, records: &mut Vec<MergeRecord>) -> Vec<Order> {
     let mut order_map = HashMap::<String, usize>::new();
     let mut res_orders: Vec<Order> = Vec::new();
 
     orders.into_iter().for_each(|order| {
         order_map
             .entry(order.id.clone())
-            .and_modify(|i| res_orders[*i].merge(&order))
+            .and_modify(|i| {
+                records.push(MergeRecord {
+                    kind: "同一订单",
+                    matched: vec!["订单编号"],
+                    phone_basis: "",
+                    score: None,
+                    into: MergeSide::new(&res_orders[*i]),
+                    merged: MergeSide::new(&order),
+                });
+                res_orders[*i].merge(&order)
+            })
             .or_insert_with(|| {
                 res_orders.push(order);
                 res_orders.len() - 1
@@ -60,116 +513,359 @@ pub fn merge_same_order(orders: Vec<Order>) -> Vec<Order> {
 
 #[derive(Debug)]
 struct PhoneAndTele {
-    phone: String,     // 联系手机
-    telephone: String, // 联系电话
+    phone: Phone,     // 联系手机
+    telephone: Phone, // 联系电话
+    buyer: String,    // 号码打码时用收货人和地址代替号码比较，否则为空
+}
+
+impl PhoneAndTele {
+    // new 使用规范化后的号码
+    fn new(order: &Order) -> PhoneAndTele {
+        let mut phones = PhoneAndTele {
+            phone: Phone::parse(&order.phone),
+            telephone: Phone::parse(&order.telephone),
+            buyer: String::new(),
+        };
+        if let Phone::Masked(_) = phones.effective() {
+            phones.buyer = format!(
+                "{}\n{}",
+                order.consignee.trim(),
+                address::normalize(&order.shipping_address)
+            );
+        }
+        phones
+    }
+
+    // effective 手机号为空时以联系电话为准
+    fn effective(&self) -> &Phone {
+        if self.phone == Phone::Empty {
+            &self.telephone
+        } else {
+            &self.phone
+        }
+    }
+
+    // basis 判断同一买家的依据，用于合并说明
+    fn basis(&self) -> &'static str {
+        match (self.effective(), self.phone == Phone::Empty) {
+            (Phone::Masked(_), _) => "收货人和地址(号码打码)",
+            (Phone::Virtual { .. }, true) => "联系电话(含分机号)",
+            (Phone::Virtual { .. }, false) => "联系手机(含分机号)",
+            (_, true) => "联系电话",
+            (_, false) => "联系手机",
+        }
+    }
 }
 
 impl PartialEq for PhoneAndTele {
     fn eq(&self, other: &Self) -> bool {
         // 手机号都为空时，以联系电话为准
         // 手机号不为空时，以手机号为准
-        match self.phone.len() {
-            0 => other.phone.len() == 0 && self.telephone == other.telephone,
-            _ => self.phone == other.phone,
+        if (self.phone == Phone::Empty) != (other.phone == Phone::Empty) {
+            return false;
+        }
+        // 隐私号需要分机号也相同；打码的号码无法比较，改为比较收货人和地址
+        match (self.effective(), other.effective()) {
+            (Phone::Masked(_), Phone::Masked(_)) => self.buyer == other.buyer,
+            (a, b) => a == b,
         }
     }
 }
 
-// 只计算 phone 的 hash，已便放入到 HashMap 中
+// 打码的号码只计算收货人和地址的 hash，已便放入到 HashMap 中
 impl Hash for PhoneAndTele {
     fn hash<H: Hasher>(&self, state: &mut H) {
-        self.phone.hash(state);
+        match self.effective() {
+            Phone::Masked(_) => self.buyer.hash(state),
+            phone => phone.hash(state),
+        }
     }
 }
 
 impl Eq for PhoneAndTele {}
 
 // mark_same_phone_order 为存在同号码的订单加上标记
-pub fn mark_same_phone_order(orders: &mut Vec<Order>) {
-    let mut phone_map = HashMap::<PhoneAndTele, usize>::new();
+pub fn mark_same_phone_order(orders: &mut [Order]) {
+    let phones: Vec<PhoneAndTele> = orders.iter().map(PhoneAndTele::new).collect();
 
-    orders.iter().for_each(|order| {
-        let count = phone_map
-            .entry(PhoneAndTele {
-                phone: order.phone.clone(),
-                telephone: order.telephone.clone(),
-            })
-            .or_insert(0);
-        *count += 1;
+    let mut phone_map = HashMap::<&PhoneAndTele, usize>::new();
+    phones.iter().for_each(|phones| {
+        *phone_map.entry(phones).or_insert(0) += 1;
     });
 
-    orders.iter_mut().for_each(|order| {
-        if phone_map[&PhoneAndTele {
-            phone: order.phone.clone(),
-            telephone: order.telephone.clone(),
-        }] > 1
-        {
-            order.has_same_phone_order = true;
-        }
-    });
+    orders
+        .iter_mut()
+        .zip(phones.iter())
+        .for_each(|(order, phones)| {
+            if phone_map[phones] > 1 {
+                order.has_same_phone_order = true;
+            }
+        });
 }
 
 #[derive(Hash, Debug, PartialEq)]
 struct OrderKey {
     consignee: String,        // 收货人
-    shipping_address: String, // 收货地址
-    status: String,           // 订单状态
+    shipping_address: String, // 规范化后的收货地址
+    status: Option<String>,   // 订单状态，不比较时为空
     phones: PhoneAndTele,     // 手机号
+    extra: Vec<String>,       // 额外比较的字段
 }
 
 impl Eq for OrderKey {}
 
-pub fn merge_diff_order(orders: Vec<Order>) -> Vec<Order> {
-    let mut order_map = HashMap::<OrderKey, usize>::new();
-    let mut res_orders: Vec<Order> = Vec::new();
+// MergeSettings 配置文件中合并同一买家的条件
+#[derive(Debug, Default, Clone, Deserialize)]
+#[serde(default)]
+pub struct MergeSettings {
+    pub ignore_status: bool, // 订单状态不同也合并
+    pub extra: Vec<String>,  // 还需要相同的字段：shop 店铺名称、buyer_account 买家会员名
+}
 
-    orders.into_iter().for_each(|order| {
-        let key = OrderKey {
+// MergeKey 合并同一买家时比较的字段，收货人、地址和号码总是比较
+#[derive(Debug, Clone, PartialEq)]
+pub struct MergeKey {
+    status: bool,      // 比较订单状态
+    extra: Vec<Field>, // 额外比较的字段
+}
+
+impl Default for MergeKey {
+    fn default() -> MergeKey {
+        MergeKey {
+            status: true,
+            extra: Vec::new(),
+        }
+    }
+}
+
+impl MergeKey {
+    pub fn new(settings: &MergeSettings) -> Result<MergeKey, String> {
+        let mut extra = Vec::new();
+        for key in settings.extra.iter() {
+            let field = match Field::from_key(key.trim()) {
+                Some(field @ (Field::Shop | Field::BuyerAccount)) => field,
+                _ => return Err(format!("unsupported merge field: {}", key)),
+            };
+            if !extra.contains(&field) {
+                extra.push(field);
+            }
+        }
+        Ok(MergeKey {
+            status: !settings.ignore_status,
+            extra,
+        })
+    }
+
+    fn value(field: Field, order: &Order) -> &str {
+        match field {
+            Field::Shop => &order.shop,
+            Field::BuyerAccount => &order.buyer_account,
+            _ => "",
+        }
+    }
+
+    fn key(&self, order: &Order) -> OrderKey {
+        OrderKey {
             consignee: order.consignee.clone(),
-            shipping_address: order.shipping_address.clone(),
-            status: order.status.clone(),
-            phones: PhoneAndTele {
-                phone: order.phone.clone(),
-                telephone: order.telephone.clone(),
-            },
-        };
-        order_map
-            .entry(key)
-            .and_modify(|i| res_orders[*i].merge_diff(&order))
-            .or_insert_with(|| {
+            shipping_address: address::normalize(&order.shipping_address),
+            status: self.status.then(|| order.status.clone()),
+            phones: PhoneAndTele::new(order),
+            extra: self
+                .extra
+                .iter()
+                .map(|field| MergeKey::value(*field, order).to_string())
+                .collect(),
+        }
+    }
+
+    // compatible 订单状态和额外字段是否满足合并条件，收货人、地址和号码另外比较
+    pub fn compatible(&self, a: &Order, b: &Order) -> bool {
+        (!self.status || a.status == b.status)
+            && self
+                .extra
+                .iter()
+                .all(|field| MergeKey::value(*field, a) == MergeKey::value(*field, b))
+    }
+
+    // extra 额外比较的字段，读取时检查这些列是否存在
+    pub fn extra(&self) -> &[Field] {
+        &self.extra
+    }
+
+    // exact_fields 除收货人、地址和号码外需要相同的字段，用于合并说明
+    pub fn exact_fields(&self) -> Vec<&'static str> {
+        let status = self.status.then_some("订单状态");
+        status
+            .into_iter()
+            .chain(self.extra.iter().map(|field| field.title()))
+            .collect()
+    }
+
+    // matched 合并说明中相同的字段
+    fn matched(&self, address: &'static str, phone_basis: &'static str) -> Vec<&'static str> {
+        let mut matched = vec!["收货人姓名", address];
+        matched.extend(self.exact_fields());
+        matched.push(phone_basis);
+        matched
+    }
+}
+
+// merge_diff_order 按 key 合并同一买家的订单，每次合并记录到 records；
+// 手动指定不合并的订单即使 key 相同也分开，两边都标记为禁合
+pub fn merge_diff_order(
+    orders: Vec<Order>,
+    key: &MergeKey,
+    overrides: &Overrides,
+    records: &mut Vec<MergeRecord>,
+) -> Vec<Order> {
+    // 同一个 key 可能因为手动指定分成多个订单
+    let mut order_map = HashMap::<OrderKey, Vec<usize>>::new();
+    let mut res_orders: Vec<Order> = Vec::new();
+
+    orders.into_iter().for_each(|mut order| {
+        let order_key = key.key(&order);
+        let phone_basis = order_key.phones.basis();
+        let candidates = order_map.entry(order_key).or_default();
+        let target = candidates
+            .iter()
+            .copied()
+            .find(|i| overrides.can_merge(res_orders[*i].ids(), order.ids()));
+        match target {
+            Some(i) => {
+                // 地址写法不同、规范化后相同时在说明中注明
+                let address = if res_orders[i].shipping_address == order.shipping_address {
+                    "收货地址"
+                } else {
+                    "收货地址(规范化后)"
+                };
+                records.push(MergeRecord {
+                    kind: "同一买家",
+                    matched: key.matched(address, phone_basis),
+                    phone_basis,
+                    score: None,
+                    into: MergeSide::new(&res_orders[i]),
+                    merged: MergeSide::new(&order),
+                });
+                res_orders[i].merge_diff(&order)
+            }
+            None => {
+                if !candidates.is_empty() {
+                    candidates.iter().for_each(|i| res_orders[*i].mark("禁合"));
+                    order.mark("禁合");
+                }
+                candidates.push(res_orders.len());
                 res_orders.push(order);
-                res_orders.len() - 1
-            });
+            }
+        }
     });
 
     res_orders
 }
 
+// force_merge 合并手动指定一定合并的订单，合并到每组中第一个出现的订单，标记为强合；
+// 已合并的订单和组内其它订单之间有不合并的要求时不合并，两边都标记为禁合
+pub fn force_merge(
+    orders: Vec<Order>,
+    overrides: &Overrides,
+    records: &mut Vec<MergeRecord>,
+) -> Vec<Order> {
+    let mut orders: Vec<Option<Order>> = orders.into_iter().map(Some).collect();
+    for ids in overrides.force_merge.iter() {
+        // 订单编号 -> 所在的订单，已经合并过的订单也能找到
+        let positions: Vec<usize> = orders
+            .iter()
+            .enumerate()
+            .filter(|(_, order)| {
+                order
+                    .as_ref()
+                    .map(|order| order.ids().any(|id| ids.iter().any(|v| v == id)))
+                    .unwrap_or(false)
+            })
+            .map(|(i, _)| i)
+            .collect();
+        let (first, rest) = match positions.split_first() {
+            Some((first, rest)) if !rest.is_empty() => (*first, rest),
+            _ => continue,
+        };
+        for i in rest.iter() {
+            let into = orders[first].as_ref().expect("first order is kept");
+            let other = orders[*i].as_ref().expect("order is merged once");
+            if !overrides.can_merge(into.ids(), other.ids()) {
+                println!(
+                    "force merge skipped, orders {} and {} are in never_merge",
+                    into.id, other.id
+                );
+                orders[first].as_mut().unwrap().mark("禁合");
+                orders[*i].as_mut().unwrap().mark("禁合");
+                continue;
+            }
+            let other = orders[*i].take().expect("order is merged once");
+            let into = orders[first].as_mut().expect("first order is kept");
+            records.push(MergeRecord {
+                kind: "手动合并",
+                matched: vec!["手动指定"],
+                phone_basis: "",
+                score: None,
+                into: MergeSide::new(into),
+                merged: MergeSide::new(&other),
+            });
+            into.merge_diff(&other);
+            into.mark("强合");
+        }
+    }
+    orders.into_iter().flatten().collect()
+}
+
 #[cfg(test)]
 mod tests {
 
     use super::*;
 
+    fn remove_repeat(orders: Vec<Order>) -> Vec<Order> {
+        let mut remove_repeat = RemoveRepeat::default();
+        let orders = orders
+            .into_iter()
+            .filter_map(|order| remove_repeat.keep(order))
+            .collect();
+        remove_repeat.finish();
+        orders
+    }
+
+    fn remove_invalid_item(orders: Vec<Order>, item_no: &str) -> Vec<Order> {
+        let matcher = ItemMatcher::new(item_no).unwrap();
+        let mut filter = ItemFilter::new(&matcher);
+        let mut res = Vec::new();
+        orders
+            .into_iter()
+            .for_each(|order| res.extend(filter.push(order)));
+        res.extend(filter.finish());
+        res
+    }
+
+    fn phones(phone: &str, telephone: &str) -> PhoneAndTele {
+        PhoneAndTele {
+            phone: Phone::parse(phone),
+            telephone: Phone::parse(telephone),
+            buyer: String::new(),
+        }
+    }
+
     #[test]
     fn test_order_key_eq() {
         assert_eq!(
             OrderKey {
                 consignee: "zhangsan".to_string(),
                 shipping_address: "shenzhen".to_string(),
-                status: "等待发货".to_string(),
-                phones: PhoneAndTele {
-                    phone: "123456789".to_string(),
-                    telephone: "".to_string(),
-                },
+                status: Some("等待发货".to_string()),
+                phones: phones("123456789", ""),
+                extra: vec![],
             },
             OrderKey {
                 consignee: "zhangsan".to_string(),
                 shipping_address: "shenzhen".to_string(),
-                status: "等待发货".to_string(),
-                phones: PhoneAndTele {
-                    phone: "123456789".to_string(),
-                    telephone: "".to_string(),
-                },
+                status: Some("等待发货".to_string()),
+                phones: phones("123456789", ""),
+                extra: vec![],
             }
         );
 
@@ -178,20 +874,16 @@ mod tests {
             OrderKey {
                 consignee: "zhangsan".to_string(),
                 shipping_address: "shenzhen".to_string(),
-                status: "等待发货".to_string(),
-                phones: PhoneAndTele {
-                    phone: "123456789".to_string(),
-                    telephone: "".to_string(),
-                },
+                status: Some("等待发货".to_string()),
+                phones: phones("123456789", ""),
+                extra: vec![],
             },
             OrderKey {
                 consignee: "zhangsan".to_string(),
                 shipping_address: "shenzhen".to_string(),
-                status: "等待发货".to_string(),
-                phones: PhoneAndTele {
-                    phone: "123456789".to_string(),
-                    telephone: "123".to_string(),
-                },
+                status: Some("等待发货".to_string()),
+                phones: phones("123456789", "123"),
+                extra: vec![],
             }
         );
 
@@ -199,20 +891,16 @@ mod tests {
             OrderKey {
                 consignee: "zhangsan".to_string(),
                 shipping_address: "shenzhen".to_string(),
-                status: "等待发货".to_string(),
-                phones: PhoneAndTele {
-                    phone: "1234567890".to_string(),
-                    telephone: "".to_string(),
-                },
+                status: Some("等待发货".to_string()),
+                phones: phones("1234567890", ""),
+                extra: vec![],
             },
             OrderKey {
                 consignee: "zhangsan".to_string(),
                 shipping_address: "shenzhen".to_string(),
-                status: "等待发货".to_string(),
-                phones: PhoneAndTele {
-                    phone: "123456789".to_string(),
-                    telephone: "123".to_string(),
-                },
+                status: Some("等待发货".to_string()),
+                phones: phones("123456789", "123"),
+                extra: vec![],
             }
         );
 
@@ -221,20 +909,16 @@ mod tests {
             OrderKey {
                 consignee: "zhangsan".to_string(),
                 shipping_address: "shenzhen".to_string(),
-                status: "等待发货".to_string(),
-                phones: PhoneAndTele {
-                    phone: "".to_string(),
-                    telephone: "123".to_string(),
-                }
+                status: Some("等待发货".to_string()),
+                phones: phones("", "123"),
+                extra: vec![],
             },
             OrderKey {
                 consignee: "zhangsan".to_string(),
                 shipping_address: "shenzhen".to_string(),
-                status: "等待发货".to_string(),
-                phones: PhoneAndTele {
-                    phone: "".to_string(),
-                    telephone: "123".to_string(),
-                }
+                status: Some("等待发货".to_string()),
+                phones: phones("", "123"),
+                extra: vec![],
             }
         );
 
@@ -242,24 +926,168 @@ mod tests {
             OrderKey {
                 consignee: "zhangsan".to_string(),
                 shipping_address: "shenzhen".to_string(),
-                status: "等待发货".to_string(),
-                phones: PhoneAndTele {
-                    phone: "".to_string(),
-                    telephone: "1234".to_string(),
-                }
+                status: Some("等待发货".to_string()),
+                phones: phones("", "1234"),
+                extra: vec![],
             },
             OrderKey {
                 consignee: "zhangsan".to_string(),
                 shipping_address: "shenzhen".to_string(),
-                status: "等待发货".to_string(),
-                phones: PhoneAndTele {
-                    phone: "".to_string(),
-                    telephone: "123".to_string(),
-                }
+                status: Some("等待发货".to_string()),
+                phones: phones("", "123"),
+                extra: vec![],
             }
         );
     }
 
+    #[test]
+    fn test_merge_key() {
+        let mut a = Order::empty();
+        a.consignee = String::from("zhangsan");
+        a.shipping_address = String::from("广东省深圳市南山区1号");
+        a.phone = String::from("13800000000");
+        a.status = String::from("等待发货");
+        a.shop = String::from("店铺A");
+        a.buyer_account = String::from("tb_zhangsan");
+        let other = |f: &dyn Fn(&mut Order)| {
+            let mut b = Order::empty();
+            b.consignee = a.consignee.clone();
+            b.shipping_address = String::from("广东省 深圳市 南山区 1号");
+            b.phone = String::from("+86 138 0000 0000");
+            b.status = a.status.clone();
+            b.shop = a.shop.clone();
+            b.buyer_account = a.buyer_account.clone();
+            f(&mut b);
+            b
+        };
+        let same = other(&|_| {});
+        let diff_status = other(&|b| b.status = String::from("已发货"));
+        let diff_shop = other(&|b| b.shop = String::from("店铺B"));
+        let diff_buyer = other(&|b| b.buyer_account = String::from("tb_lisi"));
+
+        let key = |ignore_status: bool, extra: &[&str]| {
+            MergeKey::new(&MergeSettings {
+                ignore_status,
+                extra: extra.iter().map(|v| v.to_string()).collect(),
+            })
+            .unwrap()
+        };
+        // (key, 相同, 状态不同, 店铺不同, 买家会员名不同) 是否可以合并
+        let cases = [
+            (key(false, &[]), [true, false, true, true]),
+            (key(true, &[]), [true, true, true, true]),
+            (key(false, &["shop"]), [true, false, false, true]),
+            (key(false, &["buyer_account"]), [true, false, true, false]),
+            (
+                key(false, &["shop", "买家会员名"]),
+                [true, false, false, false],
+            ),
+            (key(true, &["shop"]), [true, true, false, true]),
+            (
+                key(true, &["shop", "buyer_account"]),
+                [true, true, false, false],
+            ),
+        ];
+        assert_eq!(cases[0].0, MergeKey::default());
+        for (n, (key, expected)) in cases.iter().enumerate() {
+            for (b, expected) in [&same, &diff_status, &diff_shop, &diff_buyer]
+                .iter()
+                .zip(expected.iter())
+            {
+                assert_eq!(key.key(&a) == key.key(b), *expected, "case {}", n);
+                assert_eq!(key.compatible(&a, b), *expected, "case {}", n);
+            }
+        }
+
+        assert!(MergeKey::new(&MergeSettings {
+            ignore_status: false,
+            extra: vec![String::from("leave_msg")],
+        })
+        .is_err());
+
+        // 合并说明中列出比较的字段
+        let mut records = vec![];
+        let orders = merge_diff_order(
+            vec![other(&|_| {}), diff_status],
+            &key(true, &["shop"]),
+            &Overrides::default(),
+            &mut records,
+        );
+        assert_eq!(orders.len(), 1);
+        assert_eq!(
+            records[0].matched,
+            vec!["收货人姓名", "收货地址", "店铺名称", "联系手机"]
+        );
+    }
+
+    #[test]
+    fn test_merge_overrides() {
+        let overrides = Overrides::parse(
+            r#"
+            force_merge = [["order-1", "order-4"]]
+            never_merge = [["order-2", "order-3"]]
+            "#,
+        )
+        .unwrap();
+        let mut orders = Vec::new();
+        for (id, consignee) in [
+            ("order-1", "xiaoming"),
+            ("order-2", "xiaoming"),
+            ("order-3", "xiaoming"),
+            ("order-4", "xiaohuang"),
+        ] {
+            let mut order = Order::empty();
+            order.id = String::from(id);
+            order.consignee = String::from(consignee);
+            order.phone = String::from("13800000000");
+            orders.push(order);
+        }
+
+        // order-3 不能和 order-2 合并，也就不能并入 order-1
+        let mut records = vec![];
+        let orders = merge_diff_order(orders, &MergeKey::default(), &overrides, &mut records);
+        assert_eq!(orders.len(), 3);
+        assert_eq!(orders[0].merged, vec!["order-2"]);
+        assert_eq!(orders[0].manual, vec!["禁合"]);
+        assert_eq!(orders[1].id, "order-3");
+        assert_eq!(orders[1].manual, vec!["禁合"]);
+        assert!(orders[2].manual.is_empty());
+
+        let orders = force_merge(orders, &overrides, &mut records);
+        assert_eq!(orders.len(), 2);
+        assert_eq!(orders[0].merged, vec!["order-2", "order-4"]);
+        assert_eq!(orders[0].manual, vec!["禁合", "强合"]);
+        assert_eq!(records.last().unwrap().kind, "手动合并");
+        assert_eq!(records.last().unwrap().merged.id, "order-4");
+    }
+
+    #[test]
+    fn test_force_merge_respects_never_merge() {
+        // order-1 和 order-2 先按同一买家合并，order-2 不能和 order-3 合并
+        let overrides = Overrides::parse(
+            r#"
+            force_merge = [["order-1", "order-3"]]
+            never_merge = [["order-2", "order-3"]]
+            "#,
+        )
+        .unwrap();
+        let mut orders = Vec::new();
+        for id in ["order-1", "order-3"] {
+            let mut order = Order::empty();
+            order.id = String::from(id);
+            orders.push(order);
+        }
+        orders[0].merged.push(String::from("order-2"));
+
+        let mut records = vec![];
+        let orders = force_merge(orders, &overrides, &mut records);
+        assert_eq!(orders.len(), 2);
+        assert_eq!(orders[0].merged, vec!["order-2"]);
+        assert_eq!(orders[0].manual, vec!["禁合"]);
+        assert_eq!(orders[1].manual, vec!["禁合"]);
+        assert!(records.is_empty());
+    }
+
     #[test]
     fn test_mark_same_phone_order() {
         let mut orders = Vec::new();
@@ -316,6 +1144,40 @@ mod tests {
         assert!(orders[4].has_same_phone_order);
         assert!(orders[5].has_same_phone_order);
         assert!(!orders[6].has_same_phone_order);
+
+        // 号码规范化后比较
+        let mut orders = Vec::new();
+        for (phone, telephone) in [
+            ("+86 138-0000-0000", ""),
+            ("13800000000.0", ""),
+            ("", "0755-12345678"),
+            ("", "+86 755 1234 5678"),
+        ] {
+            let mut order = Order::empty();
+            order.phone = String::from(phone);
+            order.telephone = String::from(telephone);
+            orders.push(order);
+        }
+        mark_same_phone_order(&mut orders);
+        assert!(orders.iter().all(|order| order.has_same_phone_order));
+        // 隐私号需要分机号相同；打码的号码比较收货人和地址
+        let mut orders = Vec::new();
+        for (phone, consignee) in [
+            ("17012345678-1234", "张三"),
+            ("17012345678转1234", "张三"),
+            ("17012345678-5678", "李四"),
+            ("138****5678", "王五"),
+            ("138****5678", "王五 "),
+            ("138****5678", "赵六"),
+        ] {
+            let mut order = Order::empty();
+            order.phone = String::from(phone);
+            order.consignee = String::from(consignee);
+            orders.push(order);
+        }
+        mark_same_phone_order(&mut orders);
+        let marked: Vec<bool> = orders.iter().map(|o| o.has_same_phone_order).collect();
+        assert_eq!(marked, vec![true, true, false, true, true, false]);
     }
 
     #[test]
@@ -327,6 +1189,7 @@ mod tests {
         order.shipping_address = String::from("beijing");
         order.phone = String::from("123456789");
         order.item_name = String::from("AJ001 helloworld");
+        order.sku.item_no = String::from("AJ001");
         orders.push(order);
         let mut order = Order::empty();
         order.id = String::from("order-2");
@@ -334,6 +1197,7 @@ mod tests {
         order.shipping_address = String::from("beijing");
         order.phone = String::from("123456789");
         order.item_name = String::from("AJ003 helloworld");
+        order.sku.item_no = String::from("AJ003");
         orders.push(order);
         let mut order = Order::empty();
         order.id = String::from("order-3");
@@ -341,14 +1205,76 @@ mod tests {
         order.shipping_address = String::from("beijing");
         order.phone = String::from("123456789");
         order.item_name = String::from("AJ002 helloworld");
+        order.sku.item_no = String::from("AJ002");
         orders.push(order);
 
-        orders = merge_diff_order(orders);
+        let mut records = vec![];
+        orders = merge_diff_order(
+            orders,
+            &MergeKey::default(),
+            &Overrides::default(),
+            &mut records,
+        );
         assert_eq!(orders.len(), 2);
         assert_eq!(orders[0].id, "order-1");
         assert_eq!(orders[0].item_name, "AJ001 helloworld\nAJ003 helloworld");
         assert_eq!(orders[0].merged, vec![String::from("order-2")]);
         assert_eq!(orders[1].id, "order-3");
+
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].kind, "同一买家");
+        assert_eq!(records[0].phone_basis, "联系手机");
+        assert_eq!(records[0].into.id, "order-1");
+        assert_eq!(records[0].into.item_name, "AJ001 helloworld");
+        assert_eq!(records[0].merged.id, "order-2");
+
+        // 手机都为空时按联系电话合并
+        let mut orders = Vec::new();
+        for id in ["order-4", "order-5"] {
+            let mut order = Order::empty();
+            order.id = String::from(id);
+            order.telephone = String::from("0755-1234");
+            order.total_count = 2;
+            orders.push(order);
+        }
+        let mut records = vec![];
+        let orders = merge_diff_order(
+            orders,
+            &MergeKey::default(),
+            &Overrides::default(),
+            &mut records,
+        );
+        assert_eq!(orders.len(), 1);
+        assert_eq!(orders[0].total_count, 4);
+        assert_eq!(records[0].phone_basis, "联系电话");
+        assert_eq!(records[0].into.count, 2);
+        assert_eq!(records[0].merged.count, 2);
+
+        // 地址规范化后相同时合并，输出保留第一个订单的原始地址
+        let mut orders = Vec::new();
+        for (id, address) in [
+            ("order-6", "广东省深圳市南山区科技园1号"),
+            (
+                "order-7",
+                "广东省 深圳市 南山区 广东省深圳市南山区科技园１号 518000",
+            ),
+        ] {
+            let mut order = Order::empty();
+            order.id = String::from(id);
+            order.phone = String::from("13800000000");
+            order.shipping_address = String::from(address);
+            orders.push(order);
+        }
+        let mut records = vec![];
+        let orders = merge_diff_order(
+            orders,
+            &MergeKey::default(),
+            &Overrides::default(),
+            &mut records,
+        );
+        assert_eq!(orders.len(), 1);
+        assert_eq!(orders[0].shipping_address, "广东省深圳市南山区科技园1号");
+        assert!(records[0].matched.contains(&"收货地址(规范化后)"));
     }
 
     #[test]
@@ -357,20 +1283,53 @@ mod tests {
         let mut order = Order::empty();
         order.id = String::from("order-1");
         order.item_name = String::from("AJ001 helloworld");
+        order.sku.item_no = String::from("AJ001");
         orders.push(order);
         let mut order = Order::empty();
         order.id = String::from("order-1");
         order.item_name = String::from("AJ003 helloworld");
+        order.sku.item_no = String::from("AJ003");
         orders.push(order);
         let mut order = Order::empty();
         order.id = String::from("order-2");
         order.item_name = String::from("AJ002 helloworld");
+        order.sku.item_no = String::from("AJ002");
         orders.push(order);
 
-        orders = merge_same_order(orders);
+        let mut records = vec![];
+        orders = merge_same_order(orders, &mut records);
         assert_eq!(orders.len(), 2);
         assert_eq!(orders[0].id, "order-1");
         assert_eq!(orders[0].item_name, "AJ001 helloworld\nAJ003 helloworld");
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].kind, "同一订单");
+        assert_eq!(records[0].matched, vec!["订单编号"]);
+        assert_eq!(records[0].merged.item_name, "AJ003 helloworld");
+    }
+
+    #[test]
+    fn test_split_by_item() {
+        let mut orders = Vec::new();
+        for (id, item_no) in [
+            ("order-1", "AJ001"),
+            ("order-1", "aj002"),
+            ("order-2", "AJ001"),
+        ] {
+            let mut order = Order::empty();
+            order.id = String::from(id);
+            order.sku.item_no = String::from(item_no);
+            orders.push(order);
+        }
+
+        let batches = split_by_item(orders);
+        assert_eq!(batches.len(), 2);
+        assert_eq!(batches[0].0, "AJ001");
+        assert_eq!(batches[0].1.len(), 2);
+        assert!(batches[0].1[0].splited);
+        assert!(!batches[0].1[1].splited);
+        assert_eq!(batches[1].0, "AJ002");
+        assert_eq!(batches[1].1[0].id, "order-1");
+        assert!(batches[1].1[0].splited);
     }
 
     #[test]
@@ -380,22 +1339,152 @@ mod tests {
         let mut order = Order::empty();
         order.id = String::from("order-1");
         order.item_name = String::from("AJ001 helloworld");
+        order.sku.item_no = String::from("AJ001");
         orders.push(order);
         let mut order = Order::empty();
         order.id = String::from("order-1");
         order.item_name = String::from("AJ003 helloworld");
+        order.sku.item_no = String::from("AJ003");
         orders.push(order);
         let mut order = Order::empty();
         order.id = String::from("order-2");
         order.item_name = String::from("AJ002 helloworld");
+        order.sku.item_no = String::from("AJ002");
         orders.push(order);
 
-        orders = remove_invalid_item(orders, &String::from("aJ001"));
+        orders = remove_invalid_item(orders, "aJ001");
         assert_eq!(orders.len(), 1);
         assert_eq!(orders[0].id, "order-1");
         assert!(orders[0].splited);
     }
 
+    #[test]
+    fn test_item_filter_split_lines() {
+        let matcher = ItemMatcher::new("aj001").unwrap();
+        let mut filter = ItemFilter::new(&matcher);
+        let mut orders = Vec::new();
+        for (id, group, item_name) in [
+            ("order-1", 1, "AJ001 red"),
+            ("order-1", 1, "AJ003 blue"),
+            ("order-2", 3, "AJ002 red"),
+        ] {
+            let mut order = Order::empty();
+            order.id = String::from(id);
+            order.group = group;
+            order.item_name = String::from(item_name);
+            order.sku.item_no = item_name.split(' ').next().unwrap().to_string();
+            orders.extend(filter.push(order));
+        }
+        orders.extend(filter.finish());
+
+        assert_eq!(orders.len(), 1);
+        // 整个订单都不是条件商品的不算拆出
+        assert_eq!(filter.split_lines.len(), 1);
+        let line = &filter.split_lines[0];
+        assert_eq!(line.order.id, "order-1");
+        assert_eq!(line.order.item_name, "AJ003 blue");
+        assert_eq!(line.kept, "AJ001 red");
+        assert!(line.order.splited);
+    }
+
+    #[test]
+    fn test_item_filter_pay_amount() {
+        let matcher = ItemMatcher::new("aj001").unwrap();
+        let mut filter = ItemFilter::new(&matcher);
+        let mut orders = Vec::new();
+        // order-1 后两行沿用第一行的实付款，是整个订单的；order-2 每行都带实付款，金额相同也不分摊
+        for (id, item_no, total_price, pay_amount, pay_shared) in [
+            ("order-1", "AJ001", 6000, 9000, false),
+            ("order-1", "AJ003", 3000, 9000, true),
+            ("order-1", "AJ001", 1000, 9000, true),
+            ("order-2", "AJ001", 2990, 2990, false),
+            ("order-2", "AJ001", 2990, 2990, false),
+        ] {
+            let mut order = Order::empty();
+            order.id = String::from(id);
+            order.group = 1;
+            order.sku.item_no = String::from(item_no);
+            order.total_price = Money::from_cents(total_price);
+            order.pay_amount = Money::from_cents(pay_amount);
+            order.pay_shared = pay_shared;
+            orders.extend(filter.push(order));
+        }
+        orders.extend(filter.finish());
+
+        let pay: Vec<Money> = orders.iter().map(|order| order.pay_amount).collect();
+        assert_eq!(pay, [5400, 900, 2990, 2990].map(Money::from_cents));
+        assert_eq!(
+            filter.split_lines[0].order.pay_amount,
+            Money::from_cents(2700)
+        );
+
+        let orders = merge_same_order(orders, &mut vec![]);
+        assert_eq!(orders[0].total_price, Money::from_cents(7000));
+        assert_eq!(orders[0].pay_amount, Money::from_cents(6300));
+        assert_eq!(orders[1].pay_amount, Money::from_cents(5980));
+    }
+
+    #[test]
+    fn test_remove_repeat_duplicates() {
+        let mut remove_repeat = RemoveRepeat::default();
+        let order = |group: u32, address: &str, count: i64| {
+            let mut order = Order::empty();
+            order.id = String::from("order-1");
+            order.group = group;
+            order.source.row = group;
+            order.shipping_address = String::from(address);
+            order.item_name = String::from("AJ001 red");
+            order.total_count = count;
+            order
+        };
+        assert!(remove_repeat.keep(order(1, "shenzhen", 1)).is_some());
+        assert!(remove_repeat.keep(order(5, "shenzhen", 1)).is_none());
+        assert!(remove_repeat.keep(order(8, "shenzhen", 2)).is_none());
+        assert!(remove_repeat.keep(order(9, "beijing", 1)).is_none());
+        remove_repeat.finish();
+
+        let duplicates = &remove_repeat.duplicates;
+        assert_eq!(duplicates.len(), 3);
+        assert_eq!(duplicates[0].kept_group, 1);
+        assert_eq!(duplicates[0].kept_source.row, 1);
+        assert!(duplicates[0].diff.is_empty());
+        assert!(!duplicates[0].conflict());
+        assert_eq!(duplicates[1].diff, vec!["数量"]);
+        assert!(duplicates[1].conflict());
+        assert_eq!(duplicates[2].diff, vec!["收货信息"]);
+        assert!(duplicates[2].conflict());
+    }
+
+    #[test]
+    fn test_remove_repeat_compare_group() {
+        let mut remove_repeat = RemoveRepeat::default();
+        let order = |group: u32, item_name: &str, sheet: &str| {
+            let mut order = Order::empty();
+            order.id = String::from("order-1");
+            order.group = group;
+            order.source.sheet = String::from(sheet);
+            order.source.row = group;
+            order.item_name = String::from(item_name);
+            order.total_count = 1;
+            order
+        };
+        assert!(remove_repeat.keep(order(1, "AJ001 red", "A")).is_some());
+        assert!(remove_repeat.keep(order(1, "AJ002 blue", "A")).is_some());
+        // 商品顺序不同仍然相同
+        assert!(remove_repeat.keep(order(5, "AJ002 blue", "B")).is_none());
+        assert!(remove_repeat.keep(order(5, "AJ001 red", "B")).is_none());
+        // 少了一个商品
+        assert!(remove_repeat.keep(order(9, "AJ001 red", "B")).is_none());
+        remove_repeat.finish();
+
+        let duplicates = &remove_repeat.duplicates;
+        assert_eq!(duplicates.len(), 3);
+        assert!(duplicates[0].diff.is_empty());
+        assert!(duplicates[1].diff.is_empty());
+        assert_eq!(duplicates[1].kept_source.sheet, "A");
+        assert_eq!(duplicates[2].diff, vec!["商品"]);
+    }
+
     #[test]
     fn test_remove_repeat_on_diff_order() {
         let mut orders = Vec::new();
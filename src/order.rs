@@ -1,182 +1,496 @@
-use calamine::{self, DataType};
-use simple_excel_writer::{self as excel, sheet::Row};
-use std::collections::HashMap;
+use crate::diagnostic::Diagnostic;
+use crate::money::Money;
+use crate::phone::Phone;
+use crate::sku::Sku;
+use crate::xlsx::{self, Row};
+use calamine::{self, Data, DataType};
+use serde::Serialize;
+use std::{
+    cmp::Ordering,
+    collections::{hash_map::Entry, HashMap},
+};
 
-fn get_string(
-    item: &[DataType],
+static EMPTY: Data = Data::Empty;
+
+// cell 取出第 index 列，行比表头短时为空单元格
+fn cell(item: &[Data], index: usize) -> &Data {
+    item.get(index).unwrap_or(&EMPTY)
+}
+
+pub fn get_string(
+    item: &[Data],
     title_index: &HashMap<String, usize>,
     title: &str,
 ) -> Option<String> {
     let index = title_index.get(title)?;
-    let v = item[*index].get_string()?;
+    let v = cell(item, *index).get_string()?;
     Some(String::from(v))
 }
 
-fn get_float(item: &[DataType], title_index: &HashMap<String, usize>, title: &str) -> Option<f64> {
+// get_text 读取文本，数字单元格（如以数字保存的手机号）转换为整数字符串
+fn get_text(item: &[Data], title_index: &HashMap<String, usize>, title: &str) -> Option<String> {
+    let index = title_index.get(title)?;
+    match cell(item, *index) {
+        Data::String(v) => Some(v.clone()),
+        Data::Int(v) => Some(v.to_string()),
+        Data::Float(v) if v.fract() == 0.0 && v.abs() < MAX_EXACT_FLOAT => {
+            Some(format!("{:.0}", v))
+        }
+        Data::Float(v) => Some(v.to_string()),
+        _ => None,
+    }
+}
+
+// get_raw 返回单元格的原始内容，用于诊断信息
+pub fn get_raw(item: &[Data], title_index: &HashMap<String, usize>, title: &str) -> String {
+    match title_index.get(title) {
+        Some(index) => cell(item, *index).to_string(),
+        None => String::from("<缺少列>"),
+    }
+}
+
+fn get_float(item: &[Data], title_index: &HashMap<String, usize>, title: &str) -> Option<f64> {
+    let index = title_index.get(title)?;
+    // csv 等文本导出中的数字是字符串
+    match cell(item, *index).get_string() {
+        Some(v) => v.trim().parse().ok(),
+        None => cell(item, *index).get_float(),
+    }
+}
+
+// get_money 读取金额，文本金额可以带货币符号，如 "¥12.50"
+fn get_money(item: &[Data], title_index: &HashMap<String, usize>, title: &str) -> Option<Money> {
     let index = title_index.get(title)?;
-    let v = item[*index].get_float()?;
-    Some(v)
+    match cell(item, *index) {
+        Data::String(v) => Money::parse(v),
+        Data::Int(v) => Some(Money::from_cents(v.checked_mul(100)?)),
+        Data::Float(v) => Some(Money::from_f64(*v)),
+        _ => None,
+    }
+}
+
+// MAX_EXACT_FLOAT 2^53，小于它的整数 f64 都能精确表示；2^53+1 也会舍入为 2^53，所以不包括它本身
+pub const MAX_EXACT_FLOAT: f64 = 9007199254740992.0;
+
+// get_id 读取订单编号，单元格为空时返回 None
+// 数字单元格转换为整数字符串，无法精确表示的编号（如超过 2^53 的浮点数、科学计数法）返回错误
+pub fn get_id(
+    item: &[Data],
+    title_index: &HashMap<String, usize>,
+    title: &str,
+) -> Result<Option<String>, String> {
+    let index = match title_index.get(title) {
+        Some(index) => *index,
+        None => return Ok(None),
+    };
+    match cell(item, index) {
+        Data::Empty => Ok(None),
+        Data::String(v) if v.trim().is_empty() => Ok(None),
+        Data::String(v) => {
+            let v = v.trim();
+            let is_number = v.parse::<f64>().is_ok();
+            if is_number && v.contains(['e', 'E', '.']) {
+                return Err(format!("{} {} can't be represented exactly", title, v));
+            }
+            Ok(Some(String::from(v)))
+        }
+        Data::Int(v) => Ok(Some(v.to_string())),
+        Data::Float(v) if v.fract() == 0.0 && v.abs() < MAX_EXACT_FLOAT => {
+            Ok(Some(format!("{:.0}", v)))
+        }
+        Data::Float(v) => Err(format!("{} {} can't be represented exactly", title, v)),
+        v => Err(format!("{} {} is not a valid id", title, v)),
+    }
+}
+
+// Source 订单来源
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct Source {
+    pub file: String,  // 文件
+    pub sheet: String, // sheet 名，csv 与 html 文件为空
+    pub row: u32,      // sheet 中的行号，从 1 开始
+}
+
+// OrderLine 订单中的一个商品，合并订单时保留每一行
+#[derive(Debug, Clone, PartialEq)]
+pub struct OrderLine {
+    pub title: String, // 货品标题，不含数量
+    pub sku: Sku,      // 货号、颜色、尺码
+    pub count: i64,    // 数量
+}
+
+impl OrderLine {
+    // variant 同一款式的商品合并数量：解析出颜色或尺码时按货号、颜色、尺码，否则按货品标题
+    fn variant(&self) -> (String, String, String, String) {
+        if self.sku.color.is_empty() && self.sku.size.is_empty() {
+            (
+                String::new(),
+                String::new(),
+                String::new(),
+                self.title.clone(),
+            )
+        } else {
+            (
+                self.sku.item_no.to_uppercase(),
+                self.sku.color.clone(),
+                self.sku.size.clone(),
+                String::new(),
+            )
+        }
+    }
+}
+
+// natural_cmp 尺码等按开头的数字比较，如 90cm 排在 110cm 前面
+fn natural_cmp(a: &str, b: &str) -> Ordering {
+    let number = |v: &str| -> Option<u64> {
+        let digits: String = v.chars().take_while(|c| c.is_ascii_digit()).collect();
+        digits.parse().ok()
+    };
+    match (number(a), number(b)) {
+        (Some(x), Some(y)) if x != y => x.cmp(&y),
+        _ => a.cmp(b),
+    }
 }
 
 #[derive(Debug)]
 pub struct Order {
     pub id: String,                 // 订单编号
-    pub total_price: f64,           // 总价
-    pay_amount: f64,                // 实付款(元)
+    pub total_price: Money,         // 总价，单价*数量
+    pub pay_amount: Money,          // 实付款(元)，按商品总价分摊到每一行，合并时求和
+    pub pay_shared: bool,           // 实付款是否沿用上一行，即和上一行共用整个订单的实付款
     pub status: String,             // 订单状态
     pub consignee: String,          // 收货人
     pub shipping_address: String,   // 收货地址
     pub phone: String,              // 联系手机
     pub telephone: String,          // 联系电话
     pub item_name: String,          // 货品标题
+    pub sku: Sku,                   // 从货品标题中解析出的货号、颜色、尺码
+    pub lines: Vec<OrderLine>,      // 每个商品，合并订单时按行保留
+    pub matched_item: String,       // 匹配的货号条件
     pub total_count: i64,           // 数量
-    pub price: f64,                 // 单价
     pub leave_msg: String,          // 买家留言
+    pub shop: String,               // 店铺名称
+    pub buyer_account: String,      // 买家会员名
     pub group: u32,                 // 所属组，即该订单的第一个商品的位置
+    pub source: Source,             // 来源文件、sheet 和行号
     pub merged: Vec<String>,        // 合并了哪些订单
     pub splited: bool,              // 是否拆掉了单
     pub has_same_phone_order: bool, // 是否存在同手机号的其它订单
+    pub manual: Vec<&'static str>,  // 使用了哪些手动指定，如 强合、禁合
 }
 
 impl Order {
-    // 返回货号
-    pub fn item_no(&self) -> String {
-        match self.item_name.find(" ") {
-            None => String::from(""),
-            Some(i) => String::from(&self.item_name[..i]),
+    // contact_phone 联系手机为空时使用联系电话
+    pub fn contact_phone(&self) -> &str {
+        if self.phone.is_empty() {
+            &self.telephone
+        } else {
+            &self.phone
         }
     }
 
     pub fn excel_title_row() -> Row {
-        excel::row![
+        xlsx::row![
             "订单编号",
             "手动处理",
             "实付款(元)",
+            "总价(元)",
             "订单状态",
             "收货人姓名",
             "收货地址",
             "联系手机",
             "货品标题",
             "数量",
-            "买家留言"
+            "买家留言",
+            "货号",
+            "颜色",
+            "尺码",
+            "货号条件",
+            "原始商品"
         ]
     }
 
-    pub fn as_excel_row(&self) -> Row {
+    // flag 手动处理列的标记
+    pub fn flag(&self) -> String {
         let mut flag = String::new();
         if self.has_same_phone_order {
             flag += "未合";
         }
-        if self.merged.len() > 0 {
+        if !self.merged.is_empty() {
             flag += "已合";
         }
         if self.splited {
             flag += "已拆";
         }
-
-        let mut phone = &self.phone;
-        if phone.len() == 0 {
-            phone = &self.telephone;
-        }
-
-        let mut money = self.pay_amount;
-        // money 合并或拆分就按照数量*单价求和来
-        if self.merged.len() > 0 || self.splited {
-            money = self.total_price;
+        if !self.phone.is_empty() && !Phone::parse(&self.phone).is_valid_mobile() {
+            flag += "手机有误";
         }
+        self.manual.iter().for_each(|v| flag += v);
+        flag
+    }
 
-        excel::row![
+    pub fn as_excel_row(&self) -> Row {
+        // 同一款式合并数量后输出，原始的每一行另外保留
+        let lines = self.aggregated_lines();
+        let (item_name, sku) = if lines.is_empty() {
+            (self.item_name.clone(), self.sku.clone())
+        } else {
+            let join = |f: &dyn Fn(&OrderLine) -> String| {
+                lines.iter().map(f).collect::<Vec<String>>().join("\n")
+            };
+            (
+                join(&|line| format!("{} * {}", line.title, line.count)),
+                Sku {
+                    item_no: join(&|line| line.sku.item_no.clone()),
+                    color: join(&|line| line.sku.color.clone()),
+                    size: join(&|line| line.sku.size.clone()),
+                },
+            )
+        };
+        xlsx::row![
             self.id.clone(),
-            flag,
-            money,
+            self.flag(),
+            self.pay_amount,
+            self.total_price,
             self.status.clone(),
             self.consignee.clone(),
             self.shipping_address.clone(),
-            phone.clone(),
-            self.item_name.clone(),
+            self.contact_phone().to_string(),
+            item_name,
             self.total_count as f64,
-            self.leave_msg.clone()
+            self.leave_msg.clone(),
+            sku.item_no,
+            sku.color,
+            sku.size,
+            self.matched_item.clone(),
+            self.item_name.clone()
         ]
     }
 
     pub fn empty() -> Order {
         Order {
             id: String::from("unknow"),
-            total_price: 0.0,
-            pay_amount: 0.0,
+            total_price: Money::ZERO,
+            pay_amount: Money::ZERO,
+            pay_shared: false,
             status: String::from("unknow"),
             consignee: String::from("unknow"),
             shipping_address: String::from("unknow"),
             phone: String::from(""),
             telephone: "".to_string(),
             item_name: String::from("unknow"),
+            sku: Sku::default(),
+            lines: vec![],
+            matched_item: String::new(),
             total_count: 0,
-            price: 0.0,
             leave_msg: String::from(""),
+            shop: String::new(),
+            buyer_account: String::new(),
             group: 0,
+            source: Source::default(),
             merged: vec![],
             splited: false,
             has_same_phone_order: false,
+            manual: vec![],
         }
     }
 
+    // from_row 从一行数据中读取订单，字段缺失使用默认值时记录到 diagnostics
     pub fn from_row(
-        item: &[calamine::DataType],
+        item: &[calamine::Data],
         title_index: &HashMap<String, usize>,
         last_order: &Order,
         row_index: u32,
-    ) -> Order {
-        let total_count = get_float(item, title_index, "数量").unwrap_or(0.0) as i64;
-        let price = get_float(item, title_index, "单价(元)").unwrap_or(0.0);
+        diagnostics: &mut Vec<Diagnostic>,
+    ) -> Result<Order, String> {
+        let mut report = |title: &str, required: bool, message: String| {
+            diagnostics.push(Diagnostic {
+                file: String::new(),
+                sheet: String::new(),
+                row: row_index + 1,
+                column: String::from(title),
+                raw: get_raw(item, title_index, title),
+                message,
+                required,
+            })
+        };
+
         let mut group = row_index;
         // same_group 表示和上个订单是否为同一组
         let mut same_group = false;
-        let id = get_string(item, title_index, "订单编号").unwrap_or_else(|| {
+        // 只有订单编号为空时才属于上一个订单
+        let id = get_id(item, title_index, "订单编号")?.unwrap_or_else(|| {
             same_group = true;
             group = last_order.group;
             last_order.id.clone()
         });
+        // 第一行就没有订单编号
+        if same_group && last_order.group == 0 {
+            report("订单编号", true, format!("缺失，使用默认值 {}", id));
+        }
 
-        let order = Order {
-            id,
-            total_price: total_count as f64 * price,
-            pay_amount: get_float(item, title_index, "实付款(元)").unwrap_or(last_order.pay_amount),
-            status: get_string(item, title_index, "订单状态").unwrap_or(last_order.status.clone()),
-            consignee: get_string(item, title_index, "收货人姓名")
-                .unwrap_or(last_order.consignee.clone()),
-            shipping_address: get_string(item, title_index, "收货地址")
-                .unwrap_or(last_order.shipping_address.clone()),
-            phone: if same_group {
-                last_order.phone.clone()
-            } else {
-                get_string(item, title_index, "联系手机").unwrap_or("".to_string())
-            },
-            telephone: if same_group {
-                last_order.telephone.clone()
-            } else {
-                get_string(item, title_index, "联系电话").unwrap_or("".to_string())
-            },
+        let total_count = match get_float(item, title_index, "数量") {
+            Some(v) => v as i64,
+            None => {
+                report("数量", true, String::from("缺失，使用默认值 0"));
+                0
+            }
+        };
+        let price = match get_money(item, title_index, "单价(元)") {
+            Some(v) => v,
+            None => {
+                report("单价(元)", false, String::from("缺失，使用默认值 0"));
+                Money::ZERO
+            }
+        };
+        let item_title = match get_string(item, title_index, "货品标题") {
+            Some(v) => v,
+            None => {
+                report("货品标题", false, String::from("缺失，使用默认值 unknow"));
+                String::from("unknow")
+            }
+        };
 
-            item_name: get_string(item, title_index, "货品标题").unwrap_or(String::from("unknow"))
-                + " * "
-                + &total_count.to_string(),
+        // 同组的商品行不带订单信息，沿用上一行的值属于正常情况
+        let mut pay_shared = false;
+        let pay_amount = match get_money(item, title_index, "实付款(元)") {
+            Some(v) => v,
+            None => {
+                pay_shared = same_group;
+                if !same_group {
+                    let message = format!("缺失，沿用上一行的值 {}", last_order.pay_amount);
+                    report("实付款(元)", false, message);
+                }
+                last_order.pay_amount
+            }
+        };
+        let mut inherit = |title: &str, required: bool, last_value: &String| match get_string(
+            item,
+            title_index,
+            title,
+        ) {
+            Some(v) => v,
+            None => {
+                if !same_group {
+                    report(
+                        title,
+                        required,
+                        format!("缺失，沿用上一行的值 {}", last_value),
+                    );
+                }
+                last_value.clone()
+            }
+        };
+        let status = inherit("订单状态", false, &last_order.status);
+        let consignee = inherit("收货人姓名", true, &last_order.consignee);
+        let shipping_address = inherit("收货地址", true, &last_order.shipping_address);
+
+        let (phone, telephone) = if same_group {
+            (last_order.phone.clone(), last_order.telephone.clone())
+        } else {
+            let phone = get_text(item, title_index, "联系手机").unwrap_or_default();
+            let telephone = get_text(item, title_index, "联系电话").unwrap_or_default();
+            if phone.is_empty() && telephone.is_empty() {
+                report("联系手机", true, String::from("手机和电话都为空"));
+            }
+            (phone, telephone)
+        };
+        // 店铺和买家会员名只用于合并，缺少时为空
+        let (shop, buyer_account) = if same_group {
+            (last_order.shop.clone(), last_order.buyer_account.clone())
+        } else {
+            (
+                get_string(item, title_index, "店铺名称").unwrap_or_default(),
+                get_string(item, title_index, "买家会员名").unwrap_or_default(),
+            )
+        };
+
+        Ok(Order {
+            id,
+            total_price: price * total_count,
+            pay_amount,
+            pay_shared,
+            status,
+            consignee,
+            shipping_address,
+            phone,
+            telephone,
+            item_name: item_title + " * " + &total_count.to_string(),
+            sku: Sku::default(),
+            lines: vec![],
+            matched_item: String::new(),
             total_count,
-            price,
             leave_msg: get_string(item, title_index, "买家留言").unwrap_or(String::from("")),
+            shop,
+            buyer_account,
             group,
+            source: Source {
+                row: row_index + 1,
+                ..Source::default()
+            },
             merged: vec![],
             splited: false,
             has_same_phone_order: false,
-        };
+            manual: vec![],
+        })
+    }
 
-        order
+    // set_sku 设置解析出的 sku，并把整个订单记为一个商品，需要在平台修正货品标题之后调用
+    pub fn set_sku(&mut self, sku: Sku) {
+        let suffix = format!(" * {}", self.total_count);
+        let title = self
+            .item_name
+            .strip_suffix(&suffix)
+            .unwrap_or(&self.item_name);
+        self.lines = vec![OrderLine {
+            title: title.to_string(),
+            sku: sku.clone(),
+            count: self.total_count,
+        }];
+        self.sku = sku;
+    }
+
+    // aggregated_lines 同一款式的商品合并数量，按货号、颜色、尺码、货品标题排序，
+    // 相同时保持原来的顺序
+    pub fn aggregated_lines(&self) -> Vec<OrderLine> {
+        let mut res: Vec<OrderLine> = Vec::new();
+        let mut index = HashMap::<_, usize>::new();
+        for line in self.lines.iter() {
+            match index.entry(line.variant()) {
+                Entry::Occupied(entry) => res[*entry.get()].count += line.count,
+                Entry::Vacant(entry) => {
+                    entry.insert(res.len());
+                    res.push(line.clone());
+                }
+            }
+        }
+        res.sort_by(|a, b| {
+            let (x, y) = (&a.sku, &b.sku);
+            x.item_no
+                .to_uppercase()
+                .cmp(&y.item_no.to_uppercase())
+                .then_with(|| x.color.cmp(&y.color))
+                .then_with(|| natural_cmp(&x.size, &y.size))
+                .then_with(|| a.title.cmp(&b.title))
+        });
+        res
     }
 
     pub fn merge(&mut self, other: &Order) {
         self.item_name += &("\n".to_owned() + &other.item_name);
+        self.lines.extend(other.lines.iter().cloned());
+        self.sku.merge(&other.sku);
+        if !self
+            .matched_item
+            .split('\n')
+            .any(|v| v == other.matched_item)
+        {
+            self.matched_item += &("\n".to_owned() + &other.matched_item);
+        }
         self.total_count += other.total_count;
         self.total_price += other.total_price;
-        if self.leave_msg.len() > 0 && other.leave_msg.len() > 0 {
+        self.pay_amount += other.pay_amount;
+        if !self.leave_msg.is_empty() && !other.leave_msg.is_empty() {
             self.leave_msg += &("\n".to_owned() + &other.leave_msg);
         } else {
             self.leave_msg += &other.leave_msg;
@@ -185,6 +499,20 @@ impl Order {
     pub fn merge_diff(&mut self, other: &Order) {
         self.merge(other);
         self.merged.push(other.id.clone());
+        self.merged.extend(other.merged.iter().cloned());
+        other.manual.iter().for_each(|v| self.mark(v));
+    }
+
+    // mark 记录使用的手动指定
+    pub fn mark(&mut self, manual: &'static str) {
+        if !self.manual.contains(&manual) {
+            self.manual.push(manual);
+        }
+    }
+
+    // ids 订单编号及合并的订单编号
+    pub fn ids(&self) -> impl Iterator<Item = &str> + Clone {
+        std::iter::once(self.id.as_str()).chain(self.merged.iter().map(String::as_str))
     }
 }
 
@@ -194,16 +522,252 @@ mod tests {
     use super::*;
 
     #[test]
-    pub fn test_item_no() {
-        let mut order = Order::empty();
-        order.item_name = String::from("AJJ0 helloworld");
-        assert_eq!(order.item_no(), String::from("AJJ0"));
+    pub fn test_get_id() {
+        let title_index: HashMap<String, usize> =
+            [(String::from("订单编号"), 0)].iter().cloned().collect();
+        let get = |v: Data| get_id(&[v], &title_index, "订单编号");
+
+        assert_eq!(get(Data::Empty), Ok(None));
+        assert_eq!(get(Data::String(String::from("  "))), Ok(None));
+        assert_eq!(
+            get(Data::String(String::from(" 1232693822339834520 "))),
+            Ok(Some(String::from("1232693822339834520")))
+        );
+        assert_eq!(
+            get(Data::Int(1232693822339834520)),
+            Ok(Some(String::from("1232693822339834520")))
+        );
+        assert_eq!(
+            get(Data::Float(4818238.0)),
+            Ok(Some(String::from("4818238")))
+        );
+        assert!(get(Data::Float(1232693822339834520.0)).is_err());
+        assert!(get(Data::Float(MAX_EXACT_FLOAT)).is_err());
+        assert_eq!(
+            get(Data::Float(MAX_EXACT_FLOAT - 1.0)),
+            Ok(Some(String::from("9007199254740991")))
+        );
+        assert!(get(Data::Float(12.5)).is_err());
+        assert!(get(Data::String(String::from("1.23269E+18"))).is_err());
+        assert!(get(Data::Bool(true)).is_err());
     }
 
     #[test]
-    pub fn test_empty_item_no() {
-        let mut order = Order::empty();
-        order.item_name = String::from("AJJ0helloworld");
-        assert_eq!(order.item_no().len(), 0);
+    pub fn test_from_row_numeric_id() {
+        let title_index: HashMap<String, usize> =
+            [(String::from("订单编号"), 0), (String::from("货品标题"), 1)]
+                .iter()
+                .cloned()
+                .collect();
+
+        let first = Order::from_row(
+            &[
+                Data::Float(4818238.0),
+                Data::String(String::from("AX199 红")),
+            ],
+            &title_index,
+            &Order::empty(),
+            1,
+            &mut vec![],
+        )
+        .unwrap();
+        assert_eq!(first.id, "4818238");
+        assert_eq!(first.group, 1);
+
+        // 数字编号不再被当作上一个订单的商品
+        let second = Order::from_row(
+            &[Data::Int(4818239), Data::String(String::from("AX199 蓝"))],
+            &title_index,
+            &first,
+            2,
+            &mut vec![],
+        )
+        .unwrap();
+        assert_eq!(second.id, "4818239");
+        assert_eq!(second.group, 2);
+
+        let third = Order::from_row(
+            &[Data::Empty, Data::String(String::from("AX199 绿"))],
+            &title_index,
+            &second,
+            3,
+            &mut vec![],
+        )
+        .unwrap();
+        assert_eq!(third.id, "4818239");
+        assert_eq!(third.group, 2);
+    }
+
+    #[test]
+    pub fn test_from_row_phone() {
+        let title_index: HashMap<String, usize> =
+            [(String::from("订单编号"), 0), (String::from("联系手机"), 1)]
+                .iter()
+                .cloned()
+                .collect();
+        let read = |phone: Data| {
+            Order::from_row(
+                &[Data::Int(1001), phone],
+                &title_index,
+                &Order::empty(),
+                1,
+                &mut vec![],
+            )
+            .unwrap()
+        };
+
+        // 以数字保存的手机号
+        let order = read(Data::Float(13800000000.0));
+        assert_eq!(order.phone, "13800000000");
+        assert_eq!(order.flag(), "");
+
+        let order = read(Data::String(String::from("+86 138-0000-0000")));
+        assert_eq!(order.flag(), "");
+        let order = read(Data::String(String::from("1380000000")));
+        assert_eq!(order.flag(), "手机有误");
+    }
+
+    #[test]
+    pub fn test_from_row_money() {
+        let title_index: HashMap<String, usize> = ["订单编号", "数量", "单价(元)", "实付款(元)"]
+            .iter()
+            .enumerate()
+            .map(|(i, title)| (String::from(*title), i))
+            .collect();
+        let order = Order::from_row(
+            &[
+                Data::Int(1001),
+                Data::Float(3.0),
+                Data::String(String::from("¥59.90")),
+                Data::Float(159.7),
+            ],
+            &title_index,
+            &Order::empty(),
+            1,
+            &mut vec![],
+        )
+        .unwrap();
+        assert_eq!(order.total_price, Money::from_cents(17970));
+        assert_eq!(order.pay_amount, Money::from_cents(15970));
+        assert!(!order.pay_shared);
+
+        // 同一订单的下一行没有实付款，沿用上一行的
+        let next = Order::from_row(
+            &[
+                Data::Empty,
+                Data::Float(1.0),
+                Data::Float(10.0),
+                Data::Empty,
+            ],
+            &title_index,
+            &order,
+            2,
+            &mut vec![],
+        )
+        .unwrap();
+        assert_eq!(next.pay_amount, order.pay_amount);
+        assert!(next.pay_shared);
+
+        let mut merged = Order::empty();
+        merged.merge(&order);
+        merged.merge(&order);
+        assert_eq!(merged.total_price.to_f64(), 359.4);
+    }
+
+    #[test]
+    pub fn test_aggregated_lines() {
+        let parser = crate::sku::SkuParser::default();
+        let order = |title: &str, count: i64| {
+            let mut order = Order::empty();
+            order.total_count = count;
+            order.item_name = format!("{} * {}", title, count);
+            order.set_sku(parser.parse(&order.item_name));
+            order
+        };
+        let mut merged = order("AX199 秋衣 颜色: 红 适合身高: 110cm", 1);
+        merged.merge(&order("AX199 秋衣 颜色: 红 适合身高: 90cm", 1));
+        merged.merge(&order("AX199 秋衣 颜色: 红 适合身高: 110cm", 2));
+        merged.merge(&order("AX199 秋衣 红色款 颜色: 红 适合身高: 110cm", 1));
+        merged.merge(&order("赠品", 1));
+        merged.merge(&order("赠品", 1));
+
+        let lines: Vec<String> = merged
+            .aggregated_lines()
+            .iter()
+            .map(|line| format!("{} * {}", line.title, line.count))
+            .collect();
+        assert_eq!(
+            lines,
+            vec![
+                "AX199 秋衣 颜色: 红 适合身高: 90cm * 1",
+                "AX199 秋衣 颜色: 红 适合身高: 110cm * 4",
+                "赠品 * 2",
+            ]
+        );
+        // 原始的每一行保留在 item_name 中
+        assert_eq!(merged.item_name.lines().count(), 6);
+        assert_eq!(merged.lines.len(), 6);
+        assert_eq!(merged.total_count, 7);
+    }
+
+    #[test]
+    pub fn test_from_row_diagnostics() {
+        let title_index: HashMap<String, usize> = [
+            (String::from("订单编号"), 0),
+            (String::from("收货人姓名"), 1),
+            (String::from("收货地址"), 2),
+            (String::from("联系手机"), 3),
+            (String::from("数量"), 4),
+        ]
+        .iter()
+        .cloned()
+        .collect();
+        let s = |v: &str| Data::String(String::from(v));
+
+        let mut diagnostics = vec![];
+        let first = Order::from_row(
+            &[s("1001"), s("张三"), s("深圳"), s("13800000000"), s("2")],
+            &title_index,
+            &Order::empty(),
+            1,
+            &mut diagnostics,
+        )
+        .unwrap();
+        // 单价、货品标题、实付款、订单状态缺少列
+        assert_eq!(diagnostics.len(), 4);
+        assert!(diagnostics.iter().all(|d| !d.required && d.row == 2));
+        assert_eq!(diagnostics[0].column, "单价(元)");
+        assert_eq!(diagnostics[0].raw, "<缺少列>");
+
+        // 同组的商品行沿用订单信息，不记录
+        let mut diagnostics = vec![];
+        Order::from_row(
+            &[Data::Empty, Data::Empty, Data::Empty, Data::Empty, s("1")],
+            &title_index,
+            &first,
+            2,
+            &mut diagnostics,
+        )
+        .unwrap();
+        assert!(diagnostics.iter().all(|d| !d.required));
+
+        // 新订单缺少收货人、电话和数量
+        let mut diagnostics = vec![];
+        Order::from_row(
+            &[s("1002"), Data::Empty, s("广州"), Data::Empty, s("x")],
+            &title_index,
+            &first,
+            3,
+            &mut diagnostics,
+        )
+        .unwrap();
+        let required: Vec<&str> = diagnostics
+            .iter()
+            .filter(|d| d.required)
+            .map(|d| d.column.as_str())
+            .collect();
+        assert_eq!(required, vec!["数量", "收货人姓名", "联系手机"]);
+        assert_eq!(diagnostics[0].raw, "x");
+        assert_eq!(diagnostics[0].row, 4);
     }
 }
@@ -0,0 +1,152 @@
+use serde::Deserialize;
+use std::{collections::HashSet, fs, path::Path};
+
+// OverridesFile 手动指定文件的内容，订单编号写成字符串
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct OverridesFile {
+    force_merge: Vec<Vec<String>>, // 每组订单一定合并
+    never_merge: Vec<Vec<String>>, // 每组中任意两个订单都不合并
+    exclude: Vec<String>,          // 不输出的订单，如暂缓发货
+}
+
+// Overrides 手动指定的合并与排除
+#[derive(Debug, Default)]
+pub struct Overrides {
+    pub force_merge: Vec<Vec<String>>,
+    never_merge: HashSet<(String, String)>,
+    exclude: HashSet<String>,
+}
+
+// pair 不区分先后顺序的一对订单
+fn pair(a: &str, b: &str) -> (String, String) {
+    if a <= b {
+        (a.to_string(), b.to_string())
+    } else {
+        (b.to_string(), a.to_string())
+    }
+}
+
+impl Overrides {
+    // parse 解析 toml 格式的手动指定
+    pub fn parse(content: &str) -> Result<Overrides, String> {
+        let file: OverridesFile =
+            toml::from_str(content).map_err(|err| format!("parse overrides failed: {}", err))?;
+        let trim = |ids: &[String]| -> Vec<String> {
+            ids.iter()
+                .map(|id| id.trim().to_string())
+                .filter(|id| !id.is_empty())
+                .collect()
+        };
+
+        let mut overrides = Overrides::default();
+        for ids in file.force_merge.iter() {
+            let ids = trim(ids);
+            if ids.len() < 2 {
+                return Err(format!("force_merge needs at least 2 orders: {:?}", ids));
+            }
+            overrides.force_merge.push(ids);
+        }
+        for ids in file.never_merge.iter() {
+            let ids = trim(ids);
+            if ids.len() < 2 {
+                return Err(format!("never_merge needs at least 2 orders: {:?}", ids));
+            }
+            for (n, a) in ids.iter().enumerate() {
+                for b in ids[n + 1..].iter() {
+                    overrides.never_merge.insert(pair(a, b));
+                }
+            }
+        }
+        // 同一组一定合并的订单之间不能要求不合并
+        for ids in overrides.force_merge.iter() {
+            for (n, a) in ids.iter().enumerate() {
+                for b in ids[n + 1..].iter() {
+                    if overrides.never_merge.contains(&pair(a, b)) {
+                        return Err(format!(
+                            "orders {} and {} are in both force_merge and never_merge",
+                            a, b
+                        ));
+                    }
+                }
+            }
+        }
+        overrides.exclude = trim(&file.exclude).into_iter().collect();
+        Ok(overrides)
+    }
+
+    // load 从文件加载手动指定
+    pub fn load<P>(path: P) -> Result<Overrides, String>
+    where
+        P: AsRef<Path>,
+    {
+        let content = fs::read_to_string(&path).map_err(|err| {
+            format!(
+                "can't read overrides {}: {}",
+                path.as_ref().to_string_lossy(),
+                err
+            )
+        })?;
+        Overrides::parse(&content)
+    }
+
+    pub fn excluded(&self, id: &str) -> bool {
+        self.exclude.contains(id)
+    }
+
+    // can_merge 两组订单（合并后的订单编号及其合并的订单）之间没有不合并的要求
+    pub fn can_merge<'a, A, B>(&self, a: A, b: B) -> bool
+    where
+        A: IntoIterator<Item = &'a str>,
+        B: IntoIterator<Item = &'a str> + Clone,
+    {
+        if self.never_merge.is_empty() {
+            return true;
+        }
+        a.into_iter().all(|a| {
+            b.clone()
+                .into_iter()
+                .all(|b| !self.never_merge.contains(&pair(a, b)))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn test_overrides_parse() {
+        let overrides = Overrides::parse(
+            r#"
+            force_merge = [["1001", "1002"], ["2001", " 2002 ", "2003"]]
+            never_merge = [["3001", "3002", "3003"]]
+            exclude = ["4001"]
+            "#,
+        )
+        .unwrap();
+        assert_eq!(overrides.force_merge.len(), 2);
+        assert_eq!(overrides.force_merge[1], vec!["2001", "2002", "2003"]);
+        assert!(overrides.excluded("4001"));
+        assert!(!overrides.excluded("4002"));
+
+        assert!(!overrides.can_merge(["3002"], ["3001"]));
+        assert!(!overrides.can_merge(["1", "3003"], ["3001", "2"]));
+        assert!(overrides.can_merge(["3001"], ["1001"]));
+
+        assert!(Overrides::parse("").unwrap().can_merge(["1"], ["2"]));
+        assert!(Overrides::parse(r#"force_merge = [["1001"]]"#).is_err());
+        assert!(Overrides::parse("exclude = [4001]").is_err());
+        assert!(Overrides::parse("merge = []").is_err());
+
+        let err = Overrides::parse(
+            r#"
+            force_merge = [["1001", "1002", "1003"]]
+            never_merge = [["1003", "1001"]]
+            "#,
+        )
+        .unwrap_err();
+        assert!(err.contains("1001") && err.contains("1003"), "{}", err);
+    }
+}
@@ -0,0 +1,183 @@
+use crate::{address::fold_width, order::MAX_EXACT_FLOAT};
+
+// from_float 以数字保存的号码，如 "13800000000.0"、"1.38E+10"
+fn from_float(raw: &str) -> Option<String> {
+    if !raw.contains(['.', 'e', 'E']) {
+        return None;
+    }
+    let v: f64 = raw.parse().ok()?;
+    if v.fract() == 0.0 && v > 0.0 && v < MAX_EXACT_FLOAT {
+        Some(format!("{:.0}", v))
+    } else {
+        None
+    }
+}
+
+// normalize 规范化号码，只用于比较，输出仍使用原始号码：
+// 只保留数字和打码的 *，去掉国家代码 +86、0086、86；
+// 去掉国家代码后的固话补上区号前的 0，如 "+86 755 1234 5678" -> "075512345678"
+pub fn normalize(raw: &str) -> String {
+    let folded: String = raw.trim().chars().map(fold_width).collect();
+    let text = from_float(&folded).unwrap_or(folded);
+    let digits: String = text
+        .chars()
+        .filter(|c| c.is_ascii_digit() || *c == '*')
+        .collect();
+
+    let national = if let Some(v) = digits.strip_prefix("0086") {
+        v
+    } else if text.starts_with('+') || digits.len() >= 12 {
+        digits.strip_prefix("86").unwrap_or(&digits)
+    } else {
+        &digits
+    };
+    if national.len() != digits.len() && !national.starts_with(['0', '1']) {
+        return format!("0{}", national);
+    }
+    national.to_string()
+}
+
+// is_valid_mobile 规范化后的手机号是否为 1[3-9] 开头的 11 位数字
+pub fn is_valid_mobile(normalized: &str) -> bool {
+    let bytes = normalized.as_bytes();
+    bytes.len() == 11
+        && bytes[0] == b'1'
+        && (b'3'..=b'9').contains(&bytes[1])
+        && bytes.iter().all(u8::is_ascii_digit)
+}
+
+// Phone 号码的类型
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Phone {
+    Empty,
+    Real(String),                          // 真实号码
+    Virtual { base: String, ext: String }, // 平台隐私号或带分机的号码，如 "17012345678-1234"
+    Masked(String),                        // 打码的号码，如 "138****5678"
+}
+
+// EXTENSION_SEPARATORS 号码与分机号之间的分隔符
+const EXTENSION_SEPARATORS: [char; 6] = ['-', '转', ',', '，', '#', '_'];
+
+// split_extension 拆出分机号：最后一个分隔符后是 1 到 6 位数字，且前面是完整的手机号或带区号的固话，
+// 这样 "138-0000-0000"、"0755-12345678" 不会被当作分机号
+fn split_extension(text: &str) -> Option<(String, String)> {
+    let pos = text.rfind(EXTENSION_SEPARATORS)?;
+    let sep_len = text[pos..].chars().next()?.len_utf8();
+    let ext = text[pos + sep_len..].trim();
+    if ext.is_empty() || ext.len() > 6 || !ext.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    let base = normalize(&text[..pos]);
+    let is_landline = base.starts_with('0') && (10..=12).contains(&base.len());
+    if is_valid_mobile(&base) || is_landline {
+        Some((base, ext.to_string()))
+    } else {
+        None
+    }
+}
+
+impl Phone {
+    pub fn parse(raw: &str) -> Phone {
+        let folded: String = raw.trim().chars().map(fold_width).collect();
+        if let Some((base, ext)) = split_extension(&folded) {
+            return Phone::Virtual { base, ext };
+        }
+        let number = normalize(&folded);
+        if number.is_empty() {
+            Phone::Empty
+        } else if number.contains('*') {
+            Phone::Masked(number)
+        } else {
+            Phone::Real(number)
+        }
+    }
+
+    // is_valid_mobile 作为手机号是否有效，隐私号看主号码，打码的号码只能检查位数
+    pub fn is_valid_mobile(&self) -> bool {
+        match self {
+            Phone::Empty => false,
+            Phone::Real(number) => is_valid_mobile(number),
+            Phone::Virtual { base, .. } => is_valid_mobile(base),
+            Phone::Masked(number) => number.len() == 11 && number.starts_with('1'),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn test_normalize() {
+        for raw in [
+            "13800000000",
+            "+86 138-0000-0000",
+            "86 13800000000",
+            "0086-13800000000",
+            "13800000000.0",
+            "1.38E+10",
+            "１３８ ００００ ００００",
+        ] {
+            assert_eq!(normalize(raw), "13800000000", "{}", raw);
+        }
+
+        for raw in [
+            "0755-12345678",
+            "(0755)1234 5678",
+            "+86 755 12345678",
+            "86-755-12345678",
+            "0086 0755 12345678",
+        ] {
+            assert_eq!(normalize(raw), "075512345678", "{}", raw);
+        }
+        // 没有区号的固话不当作国家代码处理
+        assert_eq!(normalize("86123456"), "86123456");
+        assert_eq!(normalize(""), "");
+    }
+
+    #[test]
+    fn test_is_valid_mobile() {
+        assert!(is_valid_mobile(&normalize("+86 138-0000-0000")));
+        assert!(!is_valid_mobile("12800000000"));
+        assert!(!is_valid_mobile("1380000000"));
+        assert!(!is_valid_mobile("075512345678"));
+        assert!(!is_valid_mobile("138****0000"));
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(Phone::parse(" "), Phone::Empty);
+        assert_eq!(
+            Phone::parse("+86 138-0000-0000"),
+            Phone::Real(String::from("13800000000"))
+        );
+        assert_eq!(
+            Phone::parse("0755-12345678"),
+            Phone::Real(String::from("075512345678"))
+        );
+        let virtual_phone = Phone::Virtual {
+            base: String::from("17012345678"),
+            ext: String::from("1234"),
+        };
+        assert_eq!(Phone::parse("17012345678-1234"), virtual_phone);
+        assert_eq!(Phone::parse("170 1234 5678转1234"), virtual_phone);
+        assert_eq!(Phone::parse("+86 17012345678_1234"), virtual_phone);
+        assert_eq!(
+            Phone::parse("0755-12345678-801"),
+            Phone::Virtual {
+                base: String::from("075512345678"),
+                ext: String::from("801"),
+            }
+        );
+        assert_eq!(
+            Phone::parse("138****5678"),
+            Phone::Masked(String::from("138****5678"))
+        );
+
+        assert!(virtual_phone.is_valid_mobile());
+        assert!(Phone::parse("138****5678").is_valid_mobile());
+        assert!(!Phone::parse("1380000000").is_valid_mobile());
+        assert!(!Phone::parse("0755-12345678").is_valid_mobile());
+    }
+}
@@ -0,0 +1,409 @@
+use crate::mapping::Field;
+use crate::order::{get_string, Order};
+use calamine::Data;
+use std::{collections::HashMap, iter};
+
+// Platform 订单导出平台的适配器
+pub trait Platform {
+    // name 平台名称
+    fn name(&self) -> &'static str;
+
+    // names --platform 可以使用的其它名称，不区分大小写
+    fn names(&self) -> &'static [&'static str] {
+        &[]
+    }
+
+    // signature 平台导出文件中特有的列名，用于识别导出来源
+    fn signature(&self) -> &'static [&'static str];
+
+    // aliases 平台对字段使用的列名
+    fn aliases(&self, _field: Field) -> &'static [&'static str] {
+        &[]
+    }
+
+    // id_per_item 每个商品一行且每行都带订单编号，连续的同编号行属于同一订单
+    fn id_per_item(&self) -> bool {
+        false
+    }
+
+    // fix 处理平台数据的特殊情况
+    fn fix(&self, _order: &mut Order, _item: &[Data], _title_index: &HashMap<String, usize>) {}
+}
+
+// clean_cell 去掉平台为防止 Excel 转换格式而加的 ="..."、单引号和制表符
+fn clean_cell(value: &str) -> String {
+    let value = value.trim();
+    let value = value
+        .strip_prefix("=\"")
+        .and_then(|v| v.strip_suffix('"'))
+        .unwrap_or(value);
+    value.trim_start_matches('\'').trim().to_string()
+}
+
+fn clean_ids(order: &mut Order) {
+    order.id = clean_cell(&order.id);
+    order.phone = clean_cell(&order.phone);
+    order.telephone = clean_cell(&order.telephone);
+}
+
+// join_region 将拆分成省、市、区、街道的地址拼回完整地址
+fn join_region(order: &mut Order, item: &[Data], title_index: &HashMap<String, usize>) {
+    let mut parts: Vec<String> = ["省", "市", "区", "街道"]
+        .iter()
+        .filter_map(|title| get_string(item, title_index, title))
+        .map(|part| part.trim().to_string())
+        .filter(|part| !part.is_empty() && !order.shipping_address.starts_with(part.as_str()))
+        .collect();
+    if parts.is_empty() {
+        return;
+    }
+    parts.push(order.shipping_address.clone());
+    order.shipping_address = parts.join(" ");
+}
+
+// join_spec 将单独一列的商品规格拼到货品标题后面
+fn join_spec(order: &mut Order, item: &[Data], title_index: &HashMap<String, usize>) {
+    let title = match get_string(item, title_index, Field::ItemName.title()) {
+        Some(title) => title,
+        None => return,
+    };
+    let spec = ["商品规格", "样式"]
+        .iter()
+        .find_map(|title| get_string(item, title_index, title));
+    if let Some(spec) = spec {
+        order.item_name = format!("{} {} * {}", title.trim(), spec.trim(), order.total_count);
+    }
+}
+
+// Generic 未识别出平台时使用内置别名
+pub struct Generic;
+
+impl Platform for Generic {
+    fn name(&self) -> &'static str {
+        "通用"
+    }
+    fn names(&self) -> &'static [&'static str] {
+        &["generic"]
+    }
+
+    fn signature(&self) -> &'static [&'static str] {
+        &[]
+    }
+}
+
+// Alibaba 1688，内置别名即 1688 的列名
+pub struct Alibaba;
+
+impl Platform for Alibaba {
+    fn name(&self) -> &'static str {
+        "1688"
+    }
+    fn names(&self) -> &'static [&'static str] {
+        &["alibaba"]
+    }
+
+    fn signature(&self) -> &'static [&'static str] {
+        &["货品标题", "卖家会员名", "卖家公司名", "Offer ID", "发货方"]
+    }
+}
+
+// Taobao 淘宝/天猫
+pub struct Taobao;
+
+impl Platform for Taobao {
+    fn name(&self) -> &'static str {
+        "淘宝/天猫"
+    }
+    fn names(&self) -> &'static [&'static str] {
+        &["淘宝", "天猫", "taobao", "tmall"]
+    }
+
+    fn signature(&self) -> &'static [&'static str] {
+        &[
+            "宝贝标题",
+            "宝贝总数量",
+            "宝贝种类",
+            "买家实际支付金额",
+            "店铺名称",
+        ]
+    }
+
+    fn aliases(&self, field: Field) -> &'static [&'static str] {
+        match field {
+            Field::PayAmount => &["买家实际支付金额"],
+            Field::ItemName => &["宝贝标题"],
+            Field::Count => &["宝贝总数量"],
+            Field::Price => &["价格"],
+            _ => &[],
+        }
+    }
+
+    fn id_per_item(&self) -> bool {
+        true
+    }
+
+    fn fix(&self, order: &mut Order, _item: &[Data], _title_index: &HashMap<String, usize>) {
+        clean_ids(order);
+    }
+}
+
+// Pinduoduo 拼多多
+pub struct Pinduoduo;
+
+impl Platform for Pinduoduo {
+    fn name(&self) -> &'static str {
+        "拼多多"
+    }
+    fn names(&self) -> &'static [&'static str] {
+        &["pdd", "pinduoduo"]
+    }
+
+    fn signature(&self) -> &'static [&'static str] {
+        &[
+            "商品数量(件)",
+            "用户实付金额(元)",
+            "商家实收金额(元)",
+            "店铺优惠折扣(元)",
+        ]
+    }
+
+    fn aliases(&self, field: Field) -> &'static [&'static str] {
+        match field {
+            Field::Id => &["订单号"],
+            Field::PayAmount => &["用户实付金额(元)"],
+            Field::Consignee => &["收货人"],
+            Field::ShippingAddress => &["详细地址"],
+            Field::Phone => &["手机"],
+            Field::ItemName => &["商品"],
+            Field::Count => &["商品数量(件)"],
+            _ => &[],
+        }
+    }
+
+    fn id_per_item(&self) -> bool {
+        true
+    }
+
+    fn fix(&self, order: &mut Order, item: &[Data], title_index: &HashMap<String, usize>) {
+        clean_ids(order);
+        join_region(order, item, title_index);
+        join_spec(order, item, title_index);
+    }
+}
+
+// Douyin 抖店
+pub struct Douyin;
+
+impl Platform for Douyin {
+    fn name(&self) -> &'static str {
+        "抖音"
+    }
+    fn names(&self) -> &'static [&'static str] {
+        &["douyin"]
+    }
+
+    fn signature(&self) -> &'static [&'static str] {
+        &["主订单编号", "子订单编号", "选购商品", "收件人手机号"]
+    }
+
+    fn aliases(&self, field: Field) -> &'static [&'static str] {
+        match field {
+            Field::Id => &["主订单编号"],
+            Field::PayAmount => &["订单应付金额"],
+            Field::Consignee => &["收件人"],
+            Field::ShippingAddress => &["详细地址"],
+            Field::Phone => &["收件人手机号"],
+            Field::ItemName => &["选购商品"],
+            Field::Count => &["商品数量"],
+            Field::LeaveMsg => &["买家留言"],
+            _ => &[],
+        }
+    }
+
+    fn id_per_item(&self) -> bool {
+        true
+    }
+
+    fn fix(&self, order: &mut Order, item: &[Data], title_index: &HashMap<String, usize>) {
+        clean_ids(order);
+        join_region(order, item, title_index);
+        join_spec(order, item, title_index);
+    }
+}
+
+// Jingdong 京东
+pub struct Jingdong;
+
+impl Platform for Jingdong {
+    fn name(&self) -> &'static str {
+        "京东"
+    }
+    fn names(&self) -> &'static [&'static str] {
+        &["jd", "jingdong"]
+    }
+
+    fn signature(&self) -> &'static [&'static str] {
+        &["京东价", "客户姓名", "客户地址", "订购数量", "下单帐号"]
+    }
+
+    fn aliases(&self, field: Field) -> &'static [&'static str] {
+        match field {
+            Field::Id => &["订单号"],
+            Field::PayAmount => &["应付金额"],
+            Field::Consignee => &["客户姓名"],
+            Field::ShippingAddress => &["客户地址"],
+            Field::Phone => &["联系电话"],
+            Field::ItemName => &["商品名称"],
+            Field::Count => &["订购数量"],
+            Field::Price => &["京东价"],
+            Field::LeaveMsg => &["订单备注"],
+            _ => &[],
+        }
+    }
+
+    fn id_per_item(&self) -> bool {
+        true
+    }
+
+    // 京东只有联系电话一列，同时被当作手机和电话读取
+    fn fix(&self, order: &mut Order, _item: &[Data], _title_index: &HashMap<String, usize>) {
+        clean_ids(order);
+        if order.telephone == order.phone {
+            order.telephone.clear();
+        }
+    }
+}
+
+static PLATFORMS: [&(dyn Platform + Sync); 5] = [&Alibaba, &Taobao, &Pinduoduo, &Douyin, &Jingdong];
+
+// all 返回所有平台，通用平台排在最后
+pub fn all() -> impl Iterator<Item = &'static dyn Platform> {
+    PLATFORMS
+        .iter()
+        .copied()
+        .chain(iter::once(&Generic as &(dyn Platform + Sync)))
+        .map(|platform| platform as &dyn Platform)
+}
+
+// find 按名称或其它名称查找平台，需要完全相同（英文不区分大小写）
+pub fn find(name: &str) -> Result<&'static dyn Platform, String> {
+    let name = name.trim().to_lowercase();
+    if name.is_empty() {
+        return Err(String::from("platform name is empty"));
+    }
+    let found: Vec<&'static dyn Platform> = all()
+        .filter(|platform| {
+            iter::once(platform.name())
+                .chain(platform.names().iter().copied())
+                .any(|v| v.to_lowercase() == name)
+        })
+        .collect();
+    match found.as_slice() {
+        [platform] => Ok(*platform),
+        [] => Err(format!("unknown platform: {}", name)),
+        _ => Err(format!("ambiguous platform: {}", name)),
+    }
+}
+
+// detect 根据表头识别导出平台，匹配特有列名最多的平台胜出
+pub fn detect(title_index: &HashMap<String, usize>) -> &'static dyn Platform {
+    let titles: Vec<String> = title_index.keys().map(|t| t.trim().to_string()).collect();
+    let score = |platform: &dyn Platform| {
+        platform
+            .signature()
+            .iter()
+            .filter(|title| titles.iter().any(|t| t == *title))
+            .count()
+    };
+
+    let mut best: &'static dyn Platform = &Generic;
+    let mut best_score = 0;
+    for platform in PLATFORMS.iter() {
+        let s = score(*platform);
+        if s > best_score {
+            best = *platform;
+            best_score = s;
+        }
+    }
+    best
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    fn title_index(titles: &[&str]) -> HashMap<String, usize> {
+        titles
+            .iter()
+            .enumerate()
+            .map(|(i, title)| (title.to_string(), i))
+            .collect()
+    }
+
+    #[test]
+    fn test_detect() {
+        let cases: Vec<(&[&str], &str)> = vec![
+            (&["订单编号", "卖家会员名", "货品标题", "数量"], "1688"),
+            (
+                &["订单编号", "宝贝标题 ", "宝贝总数量", "买家实际支付金额"],
+                "淘宝/天猫",
+            ),
+            (
+                &["订单号", "商品", "商品数量(件)", "用户实付金额(元)"],
+                "拼多多",
+            ),
+            (
+                &["主订单编号", "子订单编号", "选购商品", "收件人手机号"],
+                "抖音",
+            ),
+            (
+                &["订单号", "商品名称", "京东价", "客户姓名", "客户地址"],
+                "京东",
+            ),
+            (&["订单编号", "收货人"], "通用"),
+        ];
+        for (titles, name) in cases {
+            assert_eq!(detect(&title_index(titles)).name(), name);
+        }
+    }
+
+    #[test]
+    fn test_find() {
+        assert_eq!(find("1688").unwrap().name(), "1688");
+        assert_eq!(find("淘宝").unwrap().name(), "淘宝/天猫");
+        assert_eq!(find("淘宝/天猫").unwrap().name(), "淘宝/天猫");
+        assert_eq!(find(" PDD ").unwrap().name(), "拼多多");
+        assert_eq!(find("JD").unwrap().name(), "京东");
+        assert!(find("amazon").is_err());
+        assert!(find("").is_err());
+        assert!(find("宝").is_err());
+        assert!(find("88").is_err());
+    }
+
+    #[test]
+    fn test_clean_cell() {
+        assert_eq!(clean_cell("=\"1234567890123\""), "1234567890123");
+        assert_eq!(clean_cell("'13800000000"), "13800000000");
+        assert_eq!(clean_cell("\t4888888888 "), "4888888888");
+    }
+
+    #[test]
+    fn test_pinduoduo_fix() {
+        let index = title_index(&["省", "市", "区", "街道", "货品标题", "商品规格"]);
+        let item = vec![
+            Data::String(String::from("广东省")),
+            Data::String(String::from("深圳市")),
+            Data::String(String::from("南山区")),
+            Data::Empty,
+            Data::String(String::from("AX199 秋衣套装")),
+            Data::String(String::from("胡萝卜 110cm")),
+        ];
+        let mut order = Order::empty();
+        order.shipping_address = String::from("科技园1号");
+        order.total_count = 2;
+        Pinduoduo.fix(&mut order, &item, &index);
+        assert_eq!(order.shipping_address, "广东省 深圳市 南山区 科技园1号");
+        assert_eq!(order.item_name, "AX199 秋衣套装 胡萝卜 110cm * 2");
+    }
+}
@@ -0,0 +1,99 @@
+use crate::fuzzy::FuzzySettings;
+use crate::opr::MergeSettings;
+use crate::sku::SkuPatterns;
+use serde::Deserialize;
+use std::{collections::HashMap, fs, path::Path};
+
+// DEFAULT_PATH 默认配置文件，存在时自动加载
+pub const DEFAULT_PATH: &str = "qlion.toml";
+
+// Settings 配置文件内容
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    pub columns: HashMap<String, Vec<String>>, // 字段 -> 列名别名
+    pub header_scan_rows: Option<usize>,       // 在前几行中查找表头
+    pub sku: SkuPatterns,                      // 解析货品标题的正则
+    pub merge: MergeSettings,                  // 合并同一买家的条件
+    pub fuzzy: FuzzySettings,                  // 疑似同一买家的阈值
+}
+
+impl Settings {
+    // parse 解析 toml 格式的配置内容
+    pub fn parse(content: &str) -> Result<Settings, String> {
+        let settings: Settings =
+            toml::from_str(content).map_err(|err| format!("parse config failed: {}", err))?;
+        settings.fuzzy.check()?;
+        Ok(settings)
+    }
+
+    // load 从文件加载配置
+    pub fn load<P>(path: P) -> Result<Settings, String>
+    where
+        P: AsRef<Path>,
+    {
+        let content = fs::read_to_string(&path).map_err(|err| {
+            format!(
+                "can't read config {}: {}",
+                path.as_ref().to_string_lossy(),
+                err
+            )
+        })?;
+        Settings::parse(&content)
+    }
+
+    // load_or_default 加载指定的配置文件；未指定时尝试默认配置文件，不存在则使用默认配置
+    pub fn load_or_default(path: Option<&str>) -> Result<Settings, String> {
+        match path {
+            Some(path) => Settings::load(path),
+            None if Path::new(DEFAULT_PATH).exists() => Settings::load(DEFAULT_PATH),
+            None => Ok(Settings::default()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn test_settings_parse() {
+        let settings = Settings::parse(
+            r#"
+            header_scan_rows = 5
+
+            [columns]
+            id = ["订单号", "主订单编号"]
+            "收货人姓名" = ["收件人"]
+
+            [sku]
+            size = '尺码[:：](\S+)'
+
+            [merge]
+            ignore_status = true
+            extra = ["shop"]
+
+            [fuzzy]
+            auto_merge = 0.95
+            "#,
+        )
+        .unwrap();
+        assert_eq!(settings.header_scan_rows, Some(5));
+        assert_eq!(settings.columns.len(), 2);
+        assert_eq!(settings.columns["id"], vec!["订单号", "主订单编号"]);
+        assert_eq!(settings.columns["收货人姓名"], vec!["收件人"]);
+        assert_eq!(settings.sku.size.as_deref(), Some(r"尺码[:：](\S+)"));
+        assert!(settings.sku.item_no.is_none());
+        assert!(settings.merge.ignore_status);
+        assert_eq!(settings.merge.extra, vec!["shop"]);
+        assert_eq!(settings.fuzzy.suggest, 0.8);
+        assert_eq!(settings.fuzzy.auto_merge, Some(0.95));
+
+        let settings = Settings::parse("").unwrap();
+        assert!(settings.columns.is_empty());
+
+        assert!(Settings::parse("[columns]\nid = 1").is_err());
+        assert!(Settings::parse("[fuzzy]\nsuggest = 0.9\nauto_merge = 0.85").is_err());
+    }
+}
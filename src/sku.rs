@@ -0,0 +1,150 @@
+use regex::Regex;
+use serde::Deserialize;
+
+// DEFAULT_ITEM_NO 货号：货品标题中第一个空格前的内容
+const DEFAULT_ITEM_NO: &str = r"^(\S+)\s";
+// DEFAULT_COLOR 颜色：如 "颜色: 胡萝卜"、"颜色分类：红色"
+const DEFAULT_COLOR: &str = r"(?:颜色分类|颜色|花色)\s*[:：]\s*([^\s;；,，*]+)";
+// DEFAULT_SIZE 尺码：如 "适合身高: 110cm"、"尺码：XL"
+const DEFAULT_SIZE: &str = r"(?:适合身高|参考身高|尺码|尺寸|身高)\s*[:：]\s*([^\s;；,，*]+)";
+
+// SkuPatterns 配置文件中的 sku 正则，未配置的使用默认正则；
+// 有捕获组时取第一个捕获组，否则取整个匹配
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct SkuPatterns {
+    pub item_no: Option<String>,
+    pub color: Option<String>,
+    pub size: Option<String>,
+}
+
+// Sku 从货品标题中解析出的商品属性，没有匹配时为空
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Sku {
+    pub item_no: String, // 货号
+    pub color: String,   // 颜色、款式
+    pub size: String,    // 尺码
+}
+
+impl Sku {
+    // merge 合并订单时货号、颜色、尺码按商品逐行拼接，与货品标题一一对应
+    pub fn merge(&mut self, other: &Sku) {
+        self.item_no += &("\n".to_owned() + &other.item_no);
+        self.color += &("\n".to_owned() + &other.color);
+        self.size += &("\n".to_owned() + &other.size);
+    }
+}
+
+// SkuParser 按正则解析货品标题
+#[derive(Debug)]
+pub struct SkuParser {
+    item_no: Regex,
+    color: Regex,
+    size: Regex,
+}
+
+fn compile(name: &str, pattern: Option<&str>, default: &str) -> Result<Regex, String> {
+    let pattern = pattern.unwrap_or(default);
+    Regex::new(pattern)
+        .map_err(|err| format!("invalid sku pattern {} {:?}: {}", name, pattern, err))
+}
+
+fn find(re: &Regex, title: &str) -> String {
+    match re.captures(title) {
+        Some(caps) => caps
+            .get(1)
+            .or_else(|| caps.get(0))
+            .map(|m| m.as_str().trim().to_string())
+            .unwrap_or_default(),
+        None => String::new(),
+    }
+}
+
+impl SkuParser {
+    pub fn new(patterns: &SkuPatterns) -> Result<SkuParser, String> {
+        Ok(SkuParser {
+            item_no: compile("item_no", patterns.item_no.as_deref(), DEFAULT_ITEM_NO)?,
+            color: compile("color", patterns.color.as_deref(), DEFAULT_COLOR)?,
+            size: compile("size", patterns.size.as_deref(), DEFAULT_SIZE)?,
+        })
+    }
+
+    pub fn parse(&self, title: &str) -> Sku {
+        Sku {
+            item_no: find(&self.item_no, title),
+            color: find(&self.color, title),
+            size: find(&self.size, title),
+        }
+    }
+}
+
+impl Default for SkuParser {
+    fn default() -> SkuParser {
+        SkuParser::new(&SkuPatterns::default()).expect("default sku patterns are valid")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn test_merge() {
+        let sku = |item_no: &str, color: &str| Sku {
+            item_no: String::from(item_no),
+            color: String::from(color),
+            size: String::new(),
+        };
+        let mut merged = sku("A", "红");
+        merged.merge(&sku("A", "蓝"));
+        merged.merge(&sku("B", "红"));
+        assert_eq!(merged.item_no, "A\nA\nB");
+        assert_eq!(merged.color, "红\n蓝\n红");
+        assert_eq!(merged.size, "\n\n");
+    }
+
+    #[test]
+    fn test_item_no() {
+        let parser = SkuParser::default();
+        assert_eq!(parser.parse("AJJ0 helloworld").item_no, "AJJ0");
+        assert_eq!(parser.parse("AJJ0helloworld").item_no, "");
+    }
+
+    #[test]
+    fn test_parse_default() {
+        let sku = SkuParser::default().parse("AX199 20色秋衣套装 颜色: 胡萝卜 适合身高: 110cm * 2");
+        assert_eq!(
+            sku,
+            Sku {
+                item_no: String::from("AX199"),
+                color: String::from("胡萝卜"),
+                size: String::from("110cm"),
+            }
+        );
+
+        let sku = SkuParser::default().parse("AX200 打底衫 颜色分类：红色;尺码：XL * 1");
+        assert_eq!(sku.color, "红色");
+        assert_eq!(sku.size, "XL");
+    }
+
+    #[test]
+    fn test_parse_configured() {
+        let parser = SkuParser::new(&SkuPatterns {
+            item_no: Some(String::from(r"款号(\w+)")),
+            color: None,
+            size: Some(String::from(r"\d+码")),
+        })
+        .unwrap();
+        let sku = parser.parse("秋衣 款号AX199 颜色: 红 120码");
+        assert_eq!(sku.item_no, "AX199");
+        assert_eq!(sku.color, "红");
+        assert_eq!(sku.size, "120码");
+
+        assert!(SkuParser::new(&SkuPatterns {
+            item_no: Some(String::from("(")),
+            ..SkuPatterns::default()
+        })
+        .is_err());
+    }
+}
@@ -0,0 +1,222 @@
+use crate::money::Money;
+use rust_xlsxwriter::{Color, Format, Workbook, Worksheet, XlsxError};
+use std::collections::HashMap;
+
+// Cell 输出到 xlsx 的单元格
+#[derive(Debug, Clone, PartialEq)]
+pub enum Cell {
+    Text(String),
+    Number(f64),
+    Money(Money), // 金额，显示两位小数
+}
+
+impl From<String> for Cell {
+    fn from(v: String) -> Cell {
+        Cell::Text(v)
+    }
+}
+
+impl From<&str> for Cell {
+    fn from(v: &str) -> Cell {
+        Cell::Text(String::from(v))
+    }
+}
+
+impl From<f64> for Cell {
+    fn from(v: f64) -> Cell {
+        Cell::Number(v)
+    }
+}
+
+impl From<Money> for Cell {
+    fn from(v: Money) -> Cell {
+        Cell::Money(v)
+    }
+}
+
+pub type Row = Vec<Cell>;
+
+// row 按顺序构造一行，如 row!["订单编号", order.id.clone(), order.total_count as f64]
+macro_rules! row {
+    ($($cell:expr),* $(,)?) => {
+        vec![$($crate::xlsx::Cell::from($cell)),*]
+    };
+}
+pub(crate) use row;
+
+// HIGHLIGHTS 手动处理列的标记对应的行背景色，同时有多个标记时取靠前的颜色
+pub const HIGHLIGHTS: [(&str, u32, &str); 3] = [
+    ("未合", 0xFFC7CE, "存在同手机号但未合并的订单，需要人工确认"),
+    ("已拆", 0xFFEB9C, "移除了非条件商品，拆出的商品见“拆出商品”"),
+    ("已合", 0xC6EFCE, "合并了其它订单，合并过程见“合并说明”"),
+];
+
+// CONFLICT 重复订单 sheet 中需要人工核对的行的背景色
+pub const CONFLICT: u32 = 0xFFC7CE;
+
+// highlight 按手动处理列的标记取行背景色
+pub fn highlight(flag: &str) -> Option<u32> {
+    HIGHLIGHTS
+        .iter()
+        .find(|(name, ..)| flag.contains(name))
+        .map(|(_, color, _)| *color)
+}
+
+// 列宽按内容估算，中文按两个字符宽
+const MIN_WIDTH: usize = 6;
+const MAX_WIDTH: usize = 60;
+
+fn text_width(text: &str) -> usize {
+    text.lines()
+        .map(|line| line.chars().map(|c| if c.is_ascii() { 1 } else { 2 }).sum())
+        .max()
+        .unwrap_or(0)
+}
+
+fn cell_width(cell: &Cell) -> usize {
+    match cell {
+        Cell::Text(v) => text_width(v),
+        Cell::Number(v) => v.to_string().len(),
+        Cell::Money(v) => v.to_string().len(),
+    }
+}
+
+// FormatKey 数据单元格的格式：背景色、是否换行、是否为金额
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+struct FormatKey {
+    color: Option<u32>,
+    wrap: bool,
+    money: bool,
+}
+
+impl FormatKey {
+    fn new(color: Option<u32>, cell: &Cell) -> FormatKey {
+        FormatKey {
+            color,
+            wrap: matches!(cell, Cell::Text(v) if v.contains('\n')),
+            money: matches!(cell, Cell::Money(_)),
+        }
+    }
+
+    fn format(self) -> Format {
+        let mut format = Format::new();
+        if let Some(color) = self.color {
+            format = format.set_background_color(Color::RGB(color));
+        }
+        if self.wrap {
+            format = format.set_text_wrap();
+        }
+        if self.money {
+            format = format.set_num_format("0.00");
+        }
+        format
+    }
+}
+
+// write_sheet 写入一个 sheet：表头加粗并冻结，带筛选，按内容设置列宽，
+// 多行文本自动换行，金额显示两位小数，指定了背景色的行整行着色
+pub fn write_sheet(
+    wb: &mut Workbook,
+    name: &str,
+    title: Row,
+    rows: Vec<(Row, Option<u32>)>,
+) -> Result<(), XlsxError> {
+    let sheet = wb.add_worksheet();
+    sheet.set_name(name)?;
+
+    // 同一种背景色、是否换行、是否为金额的单元格共用一个格式
+    let mut formats = HashMap::<FormatKey, Format>::new();
+    let bold = Format::new().set_bold();
+    let mut widths: Vec<usize> = title.iter().map(cell_width).collect();
+    for (col, cell) in title.iter().enumerate() {
+        write_cell(sheet, 0, col as u16, cell, &bold)?;
+    }
+    for (n, (row, color)) in rows.iter().enumerate() {
+        let n = n as u32 + 1;
+        for (col, cell) in row.iter().enumerate() {
+            let key = FormatKey::new(*color, cell);
+            let format = formats.entry(key).or_insert_with(|| key.format());
+            write_cell(sheet, n, col as u16, cell, format)?;
+            if col >= widths.len() {
+                widths.resize(col + 1, 0);
+            }
+            widths[col] = widths[col].max(cell_width(cell));
+        }
+    }
+
+    for (col, width) in widths.iter().enumerate() {
+        let width = (width + 2).clamp(MIN_WIDTH, MAX_WIDTH);
+        sheet.set_column_width(col as u16, width as f64)?;
+    }
+    sheet.set_freeze_panes(1, 0)?;
+    if !widths.is_empty() {
+        sheet.autofilter(0, 0, rows.len() as u32, widths.len() as u16 - 1)?;
+    }
+    Ok(())
+}
+
+fn write_cell(
+    sheet: &mut Worksheet,
+    row: u32,
+    col: u16,
+    cell: &Cell,
+    format: &Format,
+) -> Result<(), XlsxError> {
+    match cell {
+        Cell::Text(v) => sheet.write_string_with_format(row, col, v, format)?,
+        Cell::Number(v) => sheet.write_number_with_format(row, col, *v, format)?,
+        Cell::Money(v) => sheet.write_number_with_format(row, col, v.to_f64(), format)?,
+    };
+    Ok(())
+}
+
+// write_legend 写入图例 sheet，说明订单行和重复订单行背景色的含义
+pub fn write_legend(wb: &mut Workbook) -> Result<(), XlsxError> {
+    let mut rows: Vec<(Row, Option<u32>)> = HIGHLIGHTS
+        .iter()
+        .map(|(name, color, meaning)| (row![*name, *meaning], Some(*color)))
+        .collect();
+    rows.push((
+        row![
+            "需人工核对",
+            "重复订单中与首次出现的收货信息、商品或数量不同"
+        ],
+        Some(CONFLICT),
+    ));
+    write_sheet(wb, "图例", row!["标记", "说明"], rows)
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn test_highlight() {
+        assert_eq!(highlight(""), None);
+        assert_eq!(highlight("手机有误"), None);
+        assert_eq!(highlight("已合"), Some(0xC6EFCE));
+        assert_eq!(highlight("已合已拆"), Some(0xFFEB9C));
+        assert_eq!(highlight("未合已合强合"), Some(0xFFC7CE));
+    }
+
+    #[test]
+    fn test_text_width() {
+        assert_eq!(text_width(""), 0);
+        assert_eq!(text_width("AX199"), 5);
+        assert_eq!(text_width("秋衣 * 2\nAX199 颜色: 红"), 14);
+    }
+
+    #[test]
+    fn test_format_key() {
+        let money = FormatKey::new(None, &Cell::from(Money::from_cents(1250)));
+        assert!(money.money && !money.wrap);
+        assert_eq!(cell_width(&Cell::from(Money::from_cents(1250))), 5);
+        let text = FormatKey::new(Some(CONFLICT), &Cell::from("a\nb"));
+        assert!(text.wrap && !text.money);
+        assert_eq!(
+            FormatKey::new(None, &Cell::from(1.0)),
+            FormatKey::new(None, &Cell::from(2.0))
+        );
+    }
+}